@@ -1,66 +1,817 @@
 
+extern crate num;
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
 use std::io::{BufRead, BufReader};
 use std::fs::File;
 
-fn read_input() -> HashSet<isize> {
-    let mut result : HashSet<isize> = HashSet::new();
+use num::BigInt;
+
+/// Multiset of expense entries. Repeated amounts are stored as a count per value.
+#[derive(Debug, Default, Clone)]
+struct Expenses {
+    counts: HashMap<isize, usize>
+}
+
+impl Expenses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: isize) {
+        *self.counts.entry(value).or_insert(0) += 1;
+    }
+
+    /// Returns how often the value appears in the report.
+    pub fn count(&self, value: isize) -> usize {
+        self.counts.get(&value).copied().unwrap_or(0)
+    }
+
+    /// Returns all entries in ascending order, each value repeated as often as it appears.
+    pub fn sorted_entries(&self) -> Vec<isize> {
+        let mut values : Vec<isize> = self.counts.iter()
+                                          .flat_map(|(v, c)| std::iter::repeat_n(*v, *c))
+                                          .collect();
+        values.sort_unstable();
+        values
+    }
+}
+
+impl std::iter::FromIterator<isize> for Expenses {
+    fn from_iter<I: IntoIterator<Item = isize>>(iter: I) -> Self {
+        let mut expenses = Self::new();
+        for value in iter {
+            expenses.insert(value);
+        }
+        expenses
+    }
+}
+
+fn read_input() -> Expenses {
+    let mut result = Expenses::new();
 
     let f = File::open("day1/input.txt").expect("Failed to open input file");
     let reader = BufReader::new(f);
     for line_result in reader.lines() {
         let line = line_result.expect("Failed to read line");
-        let unique = result.insert(line.parse().expect("Failed to parse input"));
-        if !unique {
-            panic!("Values in input are not unique. Think of something else");
+        result.insert(line.parse().expect("Failed to parse input"));
+    }
+    result
+}
+
+/// Exact product of a tuple. Uses the smallest representation the result fits into.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+enum Product {
+    Small(isize),
+    Wide(i128),
+    Big(BigInt)
+}
+
+impl Product {
+    pub fn of(entries: &[isize]) -> Self {
+        // try with native integers first and only fall back to the wider types on overflow
+        if let Some(p) = entries.iter().try_fold(1isize, |acc, e| acc.checked_mul(*e)) {
+            Product::Small(p)
+        }else if let Some(p) = entries.iter().try_fold(1i128, |acc, e| acc.checked_mul(*e as i128)) {
+            Product::Wide(p)
+        }else{
+            Product::Big(entries.iter().map(|e| BigInt::from(*e)).product())
         }
     }
+}
+
+impl fmt::Display for Product {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Product::Small(p) => write!(f, "{}", p),
+            Product::Wide(p) => write!(f, "{}", p),
+            Product::Big(p) => write!(f, "{}", p)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct KSum {
+    entries: Vec<isize>, // ascending
+    product: Product
+}
+
+impl KSum {
+    pub fn new(mut entries: Vec<isize>) -> Self {
+        entries.sort_unstable();
+        let product = Product::of(&entries);
+        Self {
+            entries,
+            product
+        }
+    }
+}
+
+// sums of several entries may not fit into an isize, so all searches add up in i128
+fn wide_sum<'a, I: Iterator<Item = &'a isize>>(values: I) -> i128 {
+    values.map(|v| *v as i128).sum()
+}
+
+/// Finds all k-tuples of entries that sum up to target. No entry is used twice, but a value that
+///  appears multiple times in the report can appear as often in a tuple. Every tuple is reported
+///  once, with its entries in ascending order.
+fn find_k_sums(input: &Expenses, k: usize, target: isize) -> Vec<KSum> {
+    find_k_sums_wide(input, k, target as i128)
+}
+
+fn find_k_sums_wide(input: &Expenses, k: usize, target: i128) -> Vec<KSum> {
+    let values = input.sorted_entries();
+
+    let mut result = match k {
+        0 => if target == 0 { vec![KSum::new(Vec::new())] } else { Vec::new() },
+        1 => values.iter().filter(|v| **v as i128 == target).map(|v| KSum::new(vec![*v])).collect(),
+        2 => find_pairs(input, target),
+        3 => find_triples(&values, target),
+        _ => find_k_sums_mitm(&values, k, target)
+    };
+
+    result.sort();
+    result.dedup();
     result
 }
 
-fn part_one(input: &HashSet<isize>) {
+fn find_pairs(input: &Expenses, target: i128) -> Vec<KSum> {
+    let mut result = Vec::new();
+    for (entry, count) in input.counts.iter() {
+        let other_entry = match isize::try_from(target - *entry as i128) {
+            Ok(other_entry) => other_entry,
+            Err(_) => continue // can't be in the report
+        };
+        // only report the pair from its smaller entry. pairing a value with itself needs two entries
+        let found = if *entry < other_entry {
+            input.count(other_entry) > 0
+        }else{
+            *entry == other_entry && *count >= 2
+        };
+        if found {
+            result.push(KSum::new(vec![*entry, other_entry]));
+        }
+    }
+    result
+}
 
-    for entry in input {
+fn find_triples(sorted: &[isize], target: i128) -> Vec<KSum> {
+    let mut result = Vec::new();
+    for i in 0..sorted.len() {
+        // repeated values would only find the same triples again
+        if i > 0 && sorted[i] == sorted[i-1] {
+            continue;
+        }
+        // classic two-pointer scan over the values right of the first entry
+        let remainder = target - sorted[i] as i128;
+        let mut lo = i + 1;
+        let mut hi = sorted.len();
+        while lo + 1 < hi {
+            let sum = sorted[lo] as i128 + sorted[hi-1] as i128;
+            if sum < remainder {
+                lo += 1;
+            }else if sum > remainder {
+                hi -= 1;
+            }else{
+                result.push(KSum::new(vec![sorted[i], sorted[lo], sorted[hi-1]]));
+                let matched = sorted[lo];
+                while lo < hi && sorted[lo] == matched {
+                    lo += 1;
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Calls f for every ascending combination of k indices from start..n.
+fn for_each_combination<F: FnMut(&[usize])>(n: usize, k: usize, start: usize, f: &mut F) {
+    let mut indices = Vec::with_capacity(k);
+    combine(n, k, start, &mut indices, f);
+}
+
+fn combine<F: FnMut(&[usize])>(n: usize, k: usize, start: usize, indices: &mut Vec<usize>, f: &mut F) {
+    if indices.len() == k {
+        f(indices);
+        return;
+    }
+    let missing = k - indices.len();
+    for i in start..n {
+        if n - i < missing {
+            break;
+        }
+        indices.push(i);
+        combine(n, k, i + 1, indices, f);
+        indices.pop();
+    }
+}
+
+fn find_k_sums_mitm(sorted: &[isize], k: usize, target: i128) -> Vec<KSum> {
+    // split every k-tuple (in index order) into a lower half of k/2 entries and an upper half
+    //  with the rest. to make that split unique, the last index of the lower half must come before
+    //  the first index of the upper half.
+    let k_lower = k / 2;
+    let k_upper = k - k_lower;
+
+    let mut lower_halves : HashMap<i128, Vec<Vec<usize>>> = HashMap::new();
+    for_each_combination(sorted.len(), k_lower, 0, &mut |indices| {
+        let sum = wide_sum(indices.iter().map(|i| &sorted[*i]));
+        lower_halves.entry(sum).or_default().push(indices.to_vec());
+    });
+
+    let mut result = Vec::new();
+    for_each_combination(sorted.len(), k_upper, 0, &mut |indices| {
+        let sum = wide_sum(indices.iter().map(|i| &sorted[*i]));
+        if let Some(candidates) = lower_halves.get(&(target - sum)) {
+            for lower in candidates.iter().filter(|l| *l.last().unwrap() < indices[0]) {
+                let entries = lower.iter().chain(indices.iter()).map(|i| sorted[*i]).collect();
+                result.push(KSum::new(entries));
+            }
+        }
+    });
+    result
+}
+
+/// Tuples whose sum comes closest to a target from one side.
+#[derive(Debug, PartialEq)]
+struct NearestKSums {
+    sum: i128,
+    distance: i128,
+    tuples: Vec<KSum>
+}
+
+/// Finds the k-tuples with the largest sum not above target and those with the smallest sum not
+///  below target. If there is an exact match, both sides report it with a distance of zero.
+fn find_nearest_k_sums(input: &Expenses, k: usize, target: isize) -> (Option<NearestKSums>, Option<NearestKSums>) {
+    let (below, above) = find_nearest_sums(&input.sorted_entries(), k, target as i128);
+    let collect = |sum: i128| NearestKSums {
+        sum,
+        distance: (sum - target as i128).abs(),
+        tuples: find_k_sums_wide(input, k, sum)
+    };
+    (below.map(collect), above.map(collect))
+}
+
+fn find_nearest_sums(sorted: &[isize], k: usize, target: i128) -> (Option<i128>, Option<i128>) {
+    // same split as in find_k_sums_mitm, but the lower halves go into an ordered set so we can
+    //  look up the neighbours of the missing amount. sweeping the upper halves by their first
+    //  index, we only ever insert lower halves that end before it.
+    let k_lower = k / 2;
+    let k_upper = k - k_lower;
+
+    let mut lower_halves = Vec::new(); // (last index + 1, sum)
+    for_each_combination(sorted.len(), k_lower, 0, &mut |indices| {
+        let end = indices.last().map(|i| i + 1).unwrap_or(0);
+        lower_halves.push((end, wide_sum(indices.iter().map(|i| &sorted[*i]))));
+    });
+    lower_halves.sort_unstable();
+
+    let mut upper_halves = Vec::new(); // (first index, sum)
+    for_each_combination(sorted.len(), k_upper, 0, &mut |indices| {
+        let start = indices.first().copied().unwrap_or(sorted.len());
+        upper_halves.push((start, wide_sum(indices.iter().map(|i| &sorted[*i]))));
+    });
+    upper_halves.sort_unstable();
+
+    let mut below : Option<i128> = None;
+    let mut above : Option<i128> = None;
+    let mut available = BTreeSet::new();
+    let mut next_lower = 0;
+    for (start, upper_sum) in upper_halves {
+        while next_lower < lower_halves.len() && lower_halves[next_lower].0 <= start {
+            available.insert(lower_halves[next_lower].1);
+            next_lower += 1;
+        }
+        let missing = target - upper_sum;
+        if let Some(lower_sum) = available.range(..=missing).next_back() {
+            let sum = upper_sum + lower_sum;
+            below = Some(below.map_or(sum, |b| b.max(sum)));
+        }
+        if let Some(lower_sum) = available.range(missing..).next() {
+            let sum = upper_sum + lower_sum;
+            above = Some(above.map_or(sum, |a| a.min(sum)));
+        }
+    }
+    (below, above)
+}
+
+/// Fixed-size set of bits, used to track reachable sums in the subset-sum search.
+#[derive(Debug, Clone, PartialEq)]
+struct BitSet {
+    words: Vec<u64>,
+    len: usize
+}
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len
+        }
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        index < self.len && (self.words[index / 64] & (1 << (index % 64))) != 0
+    }
+
+    pub fn set(&mut self, index: usize) {
+        assert!(index < self.len);
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    /// Returns a copy with every bit moved up by shift positions (down for negative shifts). Bits
+    ///  that are moved past either end are dropped.
+    pub fn shifted(&self, shift: isize) -> Self {
+        let mut result = Self::new(self.len);
+        let word_shift = shift.unsigned_abs() / 64;
+        let bit_shift = shift.unsigned_abs() % 64;
+        let n = self.words.len();
+        for i in 0..n {
+            let word = if shift >= 0 {
+                if i < word_shift {
+                    continue;
+                }
+                let mut w = self.words[i - word_shift] << bit_shift;
+                if bit_shift > 0 && i > word_shift {
+                    w |= self.words[i - word_shift - 1] >> (64 - bit_shift);
+                }
+                w
+            }else{
+                if i + word_shift >= n {
+                    break;
+                }
+                let mut w = self.words[i + word_shift] >> bit_shift;
+                if bit_shift > 0 && i + word_shift + 1 < n {
+                    w |= self.words[i + word_shift + 1] << (64 - bit_shift);
+                }
+                w
+            };
+            result.words[i] = word;
+        }
+        // clear whatever got shifted into the unused bits of the last word
+        if !self.len.is_multiple_of(64) {
+            if let Some(last) = result.words.last_mut() {
+                *last &= (1 << (self.len % 64)) - 1;
+            }
+        }
+        result
+    }
 
-        let other_entry = 2020 - entry;
-        if let Some(_) = input.get(&other_entry) {
-            // found it!
-            let mult = entry*other_entry;
-            println!("Found {} * {} = {}", entry, other_entry, mult);
-            // keep running in case it is not the only result. if the solution is unique, we should get only two identical pairs
+    pub fn union_with(&mut self, other: &Self) {
+        for (w, o) in self.words.iter_mut().zip(other.words.iter()) {
+            *w |= *o;
         }
     }
+}
 
+/// Subset-sum search over a whole expense report. Repeated values are handled as one group each,
+///  so subsets are reported by their values and never twice.
+struct SubsetSum {
+    groups: Vec<(isize, usize)>, // value, multiplicity
+    min_sum: isize,
+    max_sum: isize,
+    reachable: Vec<BitSet> // sums reachable with the first i groups, offset by min_sum
 }
 
-fn part_two(input: &HashSet<isize>) {
+/// Largest span of sums the tables may cover, and the largest number of bits all reachability
+///  tables together may use. Anything bigger needs far too much memory.
+const MAX_SUBSET_SUM_RANGE : i128 = 1 << 24;
+const MAX_SUBSET_SUM_TABLE_BITS : i128 = 1 << 30;
 
-    // this can probably be done faster that O(n^2), but eh... just use the same strategy as in part 1
+impl SubsetSum {
+    /// Builds the reachability tables. Fails if the entries span a range of sums too large for
+    ///  the tables.
+    pub fn new(input: &Expenses) -> Result<Self, String> {
+        let mut groups : Vec<(isize, usize)> = input.counts.iter().map(|(v, c)| (*v, *c)).collect();
+        groups.sort_unstable();
 
-    for entry1 in input {
+        // the reachable sums span from all negative entries to all positive entries. done in
+        //  i128 so entries near isize::MAX can't overflow
+        let mut min_sum : i128 = 0;
+        let mut max_sum : i128 = 0;
+        for (value, count) in groups.iter() {
+            let total = *value as i128 * *count as i128;
+            if total < 0 {
+                min_sum += total;
+            }else{
+                max_sum += total;
+            }
+        }
+        let range = max_sum - min_sum + 1;
+        if range > MAX_SUBSET_SUM_RANGE || range * (groups.len() as i128 + 1) > MAX_SUBSET_SUM_TABLE_BITS {
+            return Err(format!("Subset sums span {} to {}, which is too large a range to search", min_sum, max_sum));
+        }
+        // both fit comfortably now
+        let (min_sum, max_sum, range) = (min_sum as isize, max_sum as isize, range as usize);
 
-        let remainder = 2020 - entry1;
+        let mut initial = BitSet::new(range);
+        initial.set((-min_sum) as usize);
+        let mut reachable = vec![initial];
+        for (value, count) in groups.iter() {
+            let previous = reachable.last().unwrap();
+            let mut next = previous.clone();
+            let mut taken = previous.clone();
+            for _ in 0..*count {
+                taken = taken.shifted(*value);
+                next.union_with(&taken);
+            }
+            reachable.push(next);
+        }
 
-        for entry2 in input {
+        Ok(Self {
+            groups,
+            min_sum,
+            max_sum,
+            reachable
+        })
+    }
 
-            let entry3 = remainder - entry2;
+    fn reachable_after(&self, groups: usize, sum: isize) -> bool {
+        sum >= self.min_sum && sum <= self.max_sum
+            && self.reachable[groups].get((sum - self.min_sum) as usize)
+    }
 
-            if let Some(_) = input.get(&entry3) {
-                let mult = entry1*entry2*entry3;
-                println!("Found {} * {} * {} = {}", entry1, entry2, entry3, mult);
+    /// Returns whether any non-empty subset of the entries sums up to target.
+    pub fn is_reachable(&self, target: isize) -> bool {
+        if target == 0 {
+            // the table can't tell the empty subset apart from others that cancel out
+            self.count(target) > 0
+        }else{
+            self.reachable_after(self.groups.len(), target)
+        }
+    }
+
+    /// Returns up to limit distinct non-empty subsets summing up to target, each in ascending order.
+    pub fn witnesses(&self, target: isize, limit: usize) -> Vec<Vec<isize>> {
+        let mut result = Vec::new();
+        let mut chosen = Vec::new();
+        if self.reachable_after(self.groups.len(), target) {
+            self.collect_witnesses(self.groups.len(), target, &mut chosen, &mut result, limit);
+        }
+        result
+    }
+
+    fn collect_witnesses(&self, groups: usize, sum: isize, chosen: &mut Vec<isize>, result: &mut Vec<Vec<isize>>, limit: usize) {
+        if result.len() >= limit {
+            return;
+        }
+        if groups == 0 {
+            // the reachability table guarantees we arrive at a sum of zero here
+            if !chosen.is_empty() {
+                let mut witness = chosen.clone();
+                witness.sort_unstable();
+                result.push(witness);
             }
+            return;
+        }
+        // walk back through the groups, only taking branches that can still reach the sum
+        let (value, count) = self.groups[groups - 1];
+        for taken in 0..=count {
+            let remaining = sum - value * (taken as isize);
+            if self.reachable_after(groups - 1, remaining) {
+                chosen.extend(std::iter::repeat_n(value, taken));
+                self.collect_witnesses(groups - 1, remaining, chosen, result, limit);
+                chosen.truncate(chosen.len() - taken);
+            }
+        }
+    }
 
+    /// Counts all distinct non-empty subsets summing up to target. Saturates at u128::MAX.
+    pub fn count(&self, target: isize) -> u128 {
+        if !self.reachable_after(self.groups.len(), target) {
+            return 0;
+        }
+        // without negative entries, sums can't come back down once they are past the target
+        let max_sum = if self.min_sum == 0 { target } else { self.max_sum };
+        let range = (max_sum - self.min_sum) as usize + 1;
+        let mut counts = vec![0u128; range];
+        counts[(-self.min_sum) as usize] = 1;
+        for (value, count) in self.groups.iter() {
+            let mut next = vec![0u128; range];
+            for (index, ways) in counts.iter().enumerate().filter(|(_, w)| **w > 0) {
+                let mut sum = index as isize + self.min_sum;
+                for _ in 0..=*count {
+                    if sum < self.min_sum || sum > max_sum {
+                        break;
+                    }
+                    let slot = &mut next[(sum - self.min_sum) as usize];
+                    *slot = slot.saturating_add(*ways);
+                    sum += value;
+                }
+            }
+            counts = next;
         }
+        let total = counts[(target - self.min_sum) as usize];
+        // the empty subset does not count as a solution
+        if target == 0 { total - 1 } else { total }
+    }
+}
+
+fn format_k_sum(solution: &KSum) -> String {
+    let terms = solution.entries.iter().map(|e| e.to_string()).collect::<Vec<String>>();
+    format!("{} = {}", terms.join(" * "), solution.product)
+}
 
+fn print_k_sums(input: &Expenses, k: usize, target: isize) {
+    let solutions = find_k_sums(input, k, target);
+    for solution in solutions.iter() {
+        println!("Found {}", format_k_sum(solution));
     }
+    if solutions.is_empty() {
+        println!("No {}-tuple sums up to {}", k, target);
+        let (below, above) = find_nearest_k_sums(input, k, target);
+        for (side, nearest) in [("below", below), ("above", above)].iter() {
+            if let Some(nearest) = nearest {
+                for tuple in nearest.tuples.iter() {
+                    println!("  Closest {}: sum {} (off by {}), {}", side, nearest.sum, nearest.distance, format_k_sum(tuple));
+                }
+            }
+        }
+    }
+}
+
+fn part_one(input: &Expenses, target: isize) {
+    print_k_sums(input, 2, target);
+}
+
+fn part_two(input: &Expenses, target: isize) {
+    print_k_sums(input, 3, target);
+}
 
+fn print_subsets(input: &Expenses, target: isize, limit: usize) {
+    let search = match SubsetSum::new(input) {
+        Ok(search) => search,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    if !search.is_reachable(target) {
+        println!("No subset sums up to {}", target);
+        return;
+    }
+    for witness in search.witnesses(target, limit) {
+        let terms = witness.iter().map(|e| e.to_string()).collect::<Vec<String>>();
+        println!("Found {} = {}", terms.join(" + "), target);
+    }
+    println!("{} subsets sum up to {} in total", search.count(target), target);
 }
 
 fn main() {
 
+    // usage: day1 [target]
+    //        day1 subset <target> [max witnesses]
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    let parse_target = |arg: Option<&String>| match arg {
+        Some(arg) => arg.parse().expect("Target must be an integer"),
+        None => 2020
+    };
+
     let input = read_input();
 
-    part_one(&input);
-    part_two(&input);
+    if args.first().map(|a| a.as_str()) == Some("subset") {
+        let target = parse_target(args.get(1));
+        let limit = match args.get(2) {
+            Some(arg) => arg.parse().expect("Witness limit must be a positive integer"),
+            None => 10
+        };
+        print_subsets(&input, target, limit);
+    }else{
+        let target = parse_target(args.first());
+        part_one(&input, target);
+        part_two(&input, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE : [isize; 6] = [1721, 979, 366, 299, 675, 1456];
+
+    fn example_set() -> Expenses {
+        EXAMPLE.iter().copied().collect()
+    }
+
+    #[test]
+    fn pairs_and_triples() {
+        let input = example_set();
+        assert_eq!(find_k_sums(&input, 2, 2020), vec![KSum::new(vec![299, 1721])]);
+        assert_eq!(find_k_sums(&input, 2, 2020)[0].product, Product::Small(514579));
+        assert_eq!(find_k_sums(&input, 3, 2020), vec![KSum::new(vec![366, 675, 979])]);
+        assert_eq!(find_k_sums(&input, 3, 2020)[0].product, Product::Small(241861950));
+        assert_eq!(find_k_sums(&input, 2, 1), vec![]);
+    }
+
+    #[test]
+    fn no_entry_used_twice() {
+        let input : Expenses = [1010, 675].iter().copied().collect();
+        assert_eq!(find_k_sums(&input, 2, 2020), vec![]);
+        assert_eq!(find_k_sums(&input, 3, 2025), vec![]);
+        assert_eq!(find_k_sums(&input, 2, 1685), vec![KSum::new(vec![675, 1010])]);
+    }
+
+    #[test]
+    fn duplicate_entries() {
+        let input : Expenses = [1010, 1010, 675, 675, 7].iter().copied().collect();
+        assert_eq!(input.count(1010), 2);
+        assert_eq!(input.sorted_entries(), vec![7, 675, 675, 1010, 1010]);
+        assert_eq!(find_k_sums(&input, 2, 2020), vec![KSum::new(vec![1010, 1010])]);
+        assert_eq!(find_k_sums(&input, 2, 1350), vec![KSum::new(vec![675, 675])]);
+        assert_eq!(find_k_sums(&input, 3, 2025), vec![]); // 675 only appears twice
+        assert_eq!(find_k_sums(&input, 3, 1357), vec![KSum::new(vec![7, 675, 675])]);
+        assert_eq!(find_k_sums(&input, 4, 3370), vec![KSum::new(vec![675, 675, 1010, 1010])]);
+        assert_eq!(find_k_sums(&input, 4, 2700), vec![]);
+    }
+
+    #[test]
+    fn larger_arities() {
+        let input : Expenses = (1..=10).collect();
+        assert_eq!(find_k_sums(&input, 4, 10), vec![KSum::new(vec![1, 2, 3, 4])]);
+        assert_eq!(find_k_sums(&input, 5, 40), vec![KSum::new(vec![6, 7, 8, 9, 10])]);
+        assert_eq!(find_k_sums(&input, 4, 12).len(), 2); // 1+2+3+6, 1+2+4+5
+        assert_eq!(find_k_sums(&input, 10, 55).len(), 1);
+        assert_eq!(find_k_sums(&input, 11, 55), vec![]);
+    }
+
+    #[test]
+    fn strategies_agree() {
+        let input : Expenses = (-20..30).map(|i| i * 7 % 53).chain(vec![3, 3, 3, 10, 10]).collect();
+        let values = input.sorted_entries();
+        for target in -10..60 {
+            let mut pairs = find_pairs(&input, target);
+            let mut pairs_mitm = find_k_sums_mitm(&values, 2, target);
+            pairs.sort();
+            pairs_mitm.sort();
+            pairs_mitm.dedup();
+            assert_eq!(pairs, pairs_mitm);
+
+            let mut triples = find_triples(&values, target);
+            let mut triples_mitm = find_k_sums_mitm(&values, 3, target);
+            triples.sort();
+            triples_mitm.sort();
+            triples_mitm.dedup();
+            assert_eq!(triples, triples_mitm);
+        }
+    }
+
+    fn brute_force_subsets(entries: &[isize], target: isize) -> Vec<Vec<isize>> {
+        let mut result = Vec::new();
+        for mask in 1..(1u32 << entries.len()) {
+            let mut subset : Vec<isize> = (0..entries.len()).filter(|i| mask & (1 << i) != 0)
+                                                            .map(|i| entries[i])
+                                                            .collect();
+            if subset.iter().sum::<isize>() == target {
+                subset.sort_unstable();
+                result.push(subset);
+            }
+        }
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    #[test]
+    fn bitset_shifting() {
+        let mut bits = BitSet::new(130);
+        bits.set(0);
+        bits.set(63);
+        bits.set(129);
+        let up = bits.shifted(65);
+        assert!(up.get(65) && up.get(128));
+        assert!(!up.get(0) && !up.get(63) && !up.get(129));
+        let down = bits.shifted(-63);
+        assert!(down.get(0) && down.get(66));
+        assert!(!down.get(63) && !down.get(129));
+        assert_eq!(bits.shifted(130), BitSet::new(130));
+        assert_eq!(bits.shifted(0), bits);
+    }
+
+    #[test]
+    fn subset_sums() {
+        let search = SubsetSum::new(&example_set()).unwrap();
+        let mut witnesses = search.witnesses(2020, 10);
+        witnesses.sort();
+        assert_eq!(witnesses, vec![vec![299, 1721], vec![366, 675, 979]]);
+        assert_eq!(search.witnesses(2020, 1).len(), 1);
+        assert_eq!(search.count(2020), 2);
+        assert!(search.is_reachable(5496));
+        assert!(!search.is_reachable(5497));
+        assert!(!search.is_reachable(0));
+        assert!(!search.is_reachable(-1));
+    }
+
+    #[test]
+    fn subset_sums_out_of_range() {
+        let input : Expenses = [isize::MAX, isize::MAX - 1, 1, -1, isize::MIN].iter().copied().collect();
+        assert!(SubsetSum::new(&input).is_err());
+        let input : Expenses = [isize::MAX, isize::MAX].iter().copied().collect();
+        assert!(SubsetSum::new(&input).is_err());
+        let input : Expenses = [1 << 23, 1 << 23, -(1 << 23)].iter().copied().collect();
+        assert!(SubsetSum::new(&input).is_err());
+
+        // a single large entry is fine as long as the range stays small
+        let input : Expenses = [1 << 23].iter().copied().collect();
+        assert_eq!(SubsetSum::new(&input).unwrap().count(1 << 23), 1);
+    }
+
+    #[test]
+    fn subset_sums_match_brute_force() {
+        let entries = [3, 3, 3, 5, 7, -2, -2, 11, 0, 13];
+        let search = SubsetSum::new(&entries.iter().copied().collect()).unwrap();
+        for target in -6..50 {
+            let expected = brute_force_subsets(&entries, target);
+            let mut witnesses = search.witnesses(target, usize::MAX);
+            witnesses.sort();
+            assert_eq!(witnesses, expected, "target {}", target);
+            assert_eq!(search.count(target), expected.len() as u128, "target {}", target);
+        }
+    }
+
+    #[test]
+    fn nearest_sums() {
+        let input = example_set();
+
+        let (below, above) = find_nearest_k_sums(&input, 2, 2021);
+        assert_eq!(below, Some(NearestKSums { sum: 2020, distance: 1, tuples: vec![KSum::new(vec![299, 1721])] }));
+        assert_eq!(above, Some(NearestKSums { sum: 2087, distance: 66, tuples: vec![KSum::new(vec![366, 1721])] }));
+
+        let (below, above) = find_nearest_k_sums(&input, 3, 2021);
+        assert_eq!(below.unwrap().sum, 2020);
+        assert_eq!(above.unwrap().tuples, vec![KSum::new(vec![299, 366, 1456])]);
+
+        let (below, above) = find_nearest_k_sums(&input, 4, 3000);
+        assert_eq!(below.unwrap().tuples, vec![KSum::new(vec![299, 366, 675, 1456])]);
+        assert_eq!(above.unwrap().tuples, vec![KSum::new(vec![299, 366, 675, 1721])]);
+
+        // nothing on one side
+        let (below, above) = find_nearest_k_sums(&input, 2, 10);
+        assert_eq!(below, None);
+        assert_eq!(above.unwrap().sum, 665);
+        let (below, above) = find_nearest_k_sums(&input, 2, 5000);
+        assert_eq!(below.unwrap().sum, 3177);
+        assert_eq!(above, None);
+    }
+
+    #[test]
+    fn nearest_sums_exact_and_duplicates() {
+        let input : Expenses = [1010, 1010, 5].iter().copied().collect();
+        let (below, above) = find_nearest_k_sums(&input, 2, 2020);
+        assert_eq!(below, above);
+        assert_eq!(below.unwrap().distance, 0);
+
+        // 1010 can't be used three times
+        let (below, above) = find_nearest_k_sums(&input, 3, 3030);
+        assert_eq!(below.unwrap().sum, 2025);
+        assert_eq!(above, None);
+
+        let (below, above) = find_nearest_k_sums(&input, 1, 1000);
+        assert_eq!(below.unwrap().sum, 5);
+        assert_eq!(above.unwrap().sum, 1010);
+    }
+
+    #[test]
+    fn product_overflow() {
+        assert_eq!(Product::of(&[2, 3, 7]), Product::Small(42));
+        assert_eq!(Product::of(&[]), Product::Small(1));
+        assert_eq!(Product::of(&[isize::MAX, 1]), Product::Small(isize::MAX));
+        assert_eq!(Product::of(&[isize::MIN, -1]), Product::Wide(-(isize::MIN as i128)));
+        assert_eq!(Product::of(&[isize::MAX, 2]), Product::Wide(isize::MAX as i128 * 2));
+        assert_eq!(Product::of(&[isize::MAX, isize::MIN]), Product::Wide(isize::MAX as i128 * isize::MIN as i128));
+
+        let big = Product::of(&[isize::MAX, isize::MAX, isize::MAX]);
+        let expected = BigInt::from(isize::MAX) * BigInt::from(isize::MAX) * BigInt::from(isize::MAX);
+        assert_eq!(big, Product::Big(expected.clone()));
+        assert_eq!(big.to_string(), expected.to_string());
+        assert_eq!(Product::of(&[isize::MIN, isize::MIN, -1]).to_string(), "-85070591730234615865843651857942052864");
+    }
+
+    #[test]
+    fn sums_near_limits() {
+        let input : Expenses = [isize::MAX, isize::MAX - 1, 1, -1, isize::MIN].iter().copied().collect();
+        let pairs = find_k_sums(&input, 2, isize::MAX);
+        assert_eq!(pairs, vec![KSum::new(vec![1, isize::MAX - 1])]);
+        assert_eq!(pairs[0].product, Product::Small(isize::MAX - 1));
+
+        assert_eq!(find_k_sums(&input, 3, isize::MAX - 1), vec![KSum::new(vec![-1, 1, isize::MAX - 1])]);
+        let triples = find_k_sums(&input, 3, isize::MAX - 2);
+        assert_eq!(triples, vec![KSum::new(vec![isize::MIN, isize::MAX - 1, isize::MAX])]);
+        let expected = BigInt::from(isize::MIN) * BigInt::from(isize::MAX - 1) * BigInt::from(isize::MAX);
+        assert_eq!(triples[0].product, Product::Big(expected));
+
+        let quads = find_k_sums(&input, 4, isize::MAX - 1);
+        assert_eq!(quads, vec![KSum::new(vec![isize::MIN, 1, isize::MAX - 1, isize::MAX])]);
+        assert_eq!(quads[0].product.to_string(), "-784637716923335095224261902710254454433710219057887707136");
+
+        // the closest pair doesn't fit into an isize
+        let input : Expenses = [isize::MAX, 2].iter().copied().collect();
+        let (below, above) = find_nearest_k_sums(&input, 2, isize::MAX);
+        assert_eq!(below, None);
+        let above = above.unwrap();
+        assert_eq!(above.sum, isize::MAX as i128 + 2);
+        assert_eq!(above.distance, 2);
+        assert_eq!(above.tuples[0].product, Product::Wide(isize::MAX as i128 * 2));
+    }
 }
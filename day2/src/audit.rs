@@ -0,0 +1,205 @@
+use std::io::{self, BufRead, Write};
+
+use crate::PasswordEntry;
+use crate::policy::NamedPolicy;
+
+/// A policy that an entry failed, and why.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub policy: String,
+    pub reason: String
+}
+
+/// Audit result for one line of the password database.
+#[derive(Debug, PartialEq)]
+pub struct LineReport {
+    pub line: usize, // one-based
+    pub entry: String,
+    pub result: Result<Vec<Failure>, String> // error if the line could not be parsed
+}
+
+impl LineReport {
+    pub fn status(&self) -> &'static str {
+        match &self.result {
+            Ok(failures) if failures.is_empty() => "valid",
+            Ok(_) => "invalid",
+            Err(_) => "malformed"
+        }
+    }
+}
+
+pub fn audit_line(line: usize, entry: &str, policies: &[NamedPolicy]) -> LineReport {
+    let result = PasswordEntry::parse(entry).map(|parsed| {
+        policies.iter()
+                .filter(|p| !p.policy.check(&parsed))
+                .map(|p| Failure {
+                    policy: p.name.clone(),
+                    reason: p.policy.reason(&parsed)
+                })
+                .collect()
+    });
+    LineReport {
+        line,
+        entry: entry.to_string(),
+        result
+    }
+}
+
+pub fn audit<R: BufRead>(reader: R, policies: &[NamedPolicy]) -> io::Result<Vec<LineReport>> {
+    let mut reports = Vec::new();
+    for (index, line_result) in reader.lines().enumerate() {
+        reports.push(audit_line(index + 1, &line_result?, policies));
+    }
+    Ok(reports)
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None
+        }
+    }
+}
+
+pub fn write_report<W: Write>(reports: &[LineReport], format: Format, out: &mut W) -> io::Result<()> {
+    match format {
+        Format::Csv => write_csv(reports, out),
+        Format::Json => write_json(reports, out)
+    }
+}
+
+/// Writes one row per failed policy, and a single row for lines that are valid or malformed.
+fn write_csv<W: Write>(reports: &[LineReport], out: &mut W) -> io::Result<()> {
+    writeln!(out, "line,entry,status,policy,reason")?;
+    for report in reports {
+        let entry = csv_field(&report.entry);
+        match &report.result {
+            Ok(failures) if failures.is_empty() => writeln!(out, "{},{},valid,,", report.line, entry)?,
+            Ok(failures) => {
+                for failure in failures {
+                    writeln!(out, "{},{},invalid,{},{}", report.line, entry, csv_field(&failure.policy), csv_field(&failure.reason))?;
+                }
+            },
+            Err(e) => writeln!(out, "{},{},malformed,,{}", report.line, entry, csv_field(e))?
+        }
+    }
+    Ok(())
+}
+
+/// Writes a JSON array with one object per line.
+fn write_json<W: Write>(reports: &[LineReport], out: &mut W) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (index, report) in reports.iter().enumerate() {
+        write!(out, "  {{\"line\": {}, \"entry\": {}, \"status\": \"{}\", ", report.line, json_string(&report.entry), report.status())?;
+        match &report.result {
+            Ok(failures) => {
+                let failures : Vec<String> = failures.iter()
+                                                     .map(|f| format!("{{\"policy\": {}, \"reason\": {}}}", json_string(&f.policy), json_string(&f.reason)))
+                                                     .collect();
+                write!(out, "\"failures\": [{}]}}", failures.join(", "))?;
+            },
+            Err(e) => write!(out, "\"error\": {}}}", json_string(e))?
+        }
+        writeln!(out, "{}", if index + 1 < reports.len() { "," } else { "" })?;
+    }
+    writeln!(out, "]")
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }else{
+        s.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_policies;
+
+    const DATABASE : &str = "1-3 a: abcde\n1-3 b: cdefg\nnot an entry\n2-9 c: ccccccccc\n";
+
+    fn write(format: Format) -> String {
+        let reports = audit(DATABASE.as_bytes(), &default_policies()).unwrap();
+        let mut out = Vec::new();
+        write_report(&reports, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn line_reports() {
+        let reports = audit(DATABASE.as_bytes(), &default_policies()).unwrap();
+        assert_eq!(reports.len(), 4);
+        assert_eq!(reports[0].result, Ok(vec![]));
+        assert_eq!(reports[1].result, Ok(vec![
+            Failure { policy: "policy 1".to_string(), reason: "count of 'b' is 0, allowed 1-3".to_string() },
+            Failure { policy: "policy 2".to_string(), reason: "character at position 1 is 'c', expected 'b'; character at position 3 is 'e', expected 'b'".to_string() }
+        ]));
+        assert_eq!(reports[2].line, 3);
+        assert_eq!(reports[2].status(), "malformed");
+        assert_eq!(reports[3].status(), "invalid");
+    }
+
+    #[test]
+    fn csv_output() {
+        let csv = write(Format::Csv);
+        let lines : Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "line,entry,status,policy,reason");
+        assert_eq!(lines[1], "1,1-3 a: abcde,valid,,");
+        assert_eq!(lines[2], "2,1-3 b: cdefg,invalid,policy 1,\"count of 'b' is 0, allowed 1-3\"");
+        assert_eq!(lines[3], "2,1-3 b: cdefg,invalid,policy 2,\"character at position 1 is 'c', expected 'b'; character at position 3 is 'e', expected 'b'\"");
+        assert_eq!(lines[4], "3,not an entry,malformed,,\"Entry does not look like \"\"min-max c: password\"\"\"");
+        assert_eq!(lines[5], "4,2-9 c: ccccccccc,invalid,policy 2,\"character at position 2 is 'c' and character at position 9 is 'c', but only one may hold\"");
+        assert_eq!(lines.len(), 6);
+    }
+
+    #[test]
+    fn json_output() {
+        let json = write(Format::Json);
+        let lines : Vec<&str> = json.lines().collect();
+        assert_eq!(lines[0], "[");
+        assert_eq!(lines[1], "  {\"line\": 1, \"entry\": \"1-3 a: abcde\", \"status\": \"valid\", \"failures\": []},");
+        assert!(lines[2].contains("{\"policy\": \"policy 1\", \"reason\": \"count of 'b' is 0, allowed 1-3\"}"));
+        assert!(lines[2].contains("{\"policy\": \"policy 2\", \"reason\": \"character at position 1 is 'c', expected 'b'; character at position 3 is 'e', expected 'b'\"}"));
+        assert_eq!(lines[3], "  {\"line\": 3, \"entry\": \"not an entry\", \"status\": \"malformed\", \"error\": \"Entry does not look like \\\"min-max c: password\\\"\"},");
+        assert!(lines[4].ends_with("]}"));
+        assert_eq!(lines[5], "]");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::fs::File;
+use std::time::Instant;
+
+use crate::PasswordEntry;
+use crate::generator::Rng;
+use crate::policy::NamedPolicy;
+use crate::stream;
+
+const CHUNK_SIZE : usize = 1 << 20;
+
+/// Writes roughly the given amount of random database lines that look like the puzzle input.
+pub fn generate_dump<W: Write>(out: &mut W, bytes: usize, seed: u64) -> io::Result<()> {
+    let mut rng = Rng::new(seed);
+    let mut written = 0;
+    let mut line = Vec::with_capacity(64);
+    while written < bytes {
+        line.clear();
+        let first = 1 + rng.below(10);
+        let second = first + 1 + rng.below(10);
+        let required = b'a' + rng.below(26) as u8;
+        write!(line, "{}-{} {}: ", first, second, required as char)?;
+        for _ in 0..(second + rng.below(5)) {
+            // bias towards the required letter so both outcomes are common
+            if rng.below(4) == 0 {
+                line.push(required);
+            }else{
+                line.push(b'a' + rng.below(26) as u8);
+            }
+        }
+        line.push(b'\n');
+        out.write_all(&line)?;
+        written += line.len();
+    }
+    out.flush()
+}
+
+fn report(name: &str, bytes: u64, start: Instant, counts: &stream::Counts) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!("{:<24} {:>8.3} s {:>10.1} MB/s   {} entries, valid {:?}",
+        name, seconds, bytes as f64 / seconds / 1e6, counts.entries, counts.valid);
+}
+
+/// Validates line by line with the regex parser. Empty lines are skipped, same as in
+///  stream::validate_chunk, so both do the same work.
+fn validate_lines<R: BufRead>(reader: R, policies: &[NamedPolicy]) -> io::Result<stream::Counts> {
+    let mut counts = stream::Counts::new(policies.len());
+    for line_result in reader.lines() {
+        let line = line_result?;
+        if line.is_empty() {
+            continue;
+        }
+        match PasswordEntry::parse(&line) {
+            Ok(entry) => {
+                counts.entries += 1;
+                for (policy, valid) in policies.iter().zip(counts.valid.iter_mut()) {
+                    if policy.policy.check(&entry) {
+                        *valid += 1;
+                    }
+                }
+            },
+            Err(_) => counts.malformed += 1
+        }
+    }
+    Ok(counts)
+}
+
+/// Validates the file at path with the regex parser, and with the byte parser on one and on all
+///  available threads, printing the throughput of each.
+pub fn run(path: &str, policies: &[NamedPolicy]) -> io::Result<()> {
+    let bytes = std::fs::metadata(path)?.len();
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    println!("Benchmarking on {} ({:.1} MB)", path, bytes as f64 / 1e6);
+
+    let start = Instant::now();
+    let expected = validate_lines(BufReader::new(File::open(path)?), policies)?;
+    report("regex, line by line", bytes, start, &expected);
+
+    let start = Instant::now();
+    let counts = stream::validate_parallel(&mut File::open(path)?, policies, 1, CHUNK_SIZE)?;
+    report("bytes, 1 thread", bytes, start, &counts);
+    assert_eq!(counts, expected, "Byte parser disagrees with the regex parser");
+
+    let start = Instant::now();
+    let counts = stream::validate_parallel(&mut File::open(path)?, policies, threads, CHUNK_SIZE)?;
+    report(&format!("bytes, {} threads", threads), bytes, start, &counts);
+    assert_eq!(counts, expected, "Byte parser disagrees with the regex parser");
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_policies;
+
+    #[test]
+    fn generated_dump() {
+        let mut dump = Vec::new();
+        generate_dump(&mut dump, 10000, 42).unwrap();
+        assert!(dump.len() >= 10000);
+
+        let mut again = Vec::new();
+        generate_dump(&mut again, 10000, 42).unwrap();
+        assert_eq!(dump, again);
+
+        // every generated line is well-formed, and the regex parser agrees with the byte parser
+        let policies = default_policies();
+        for line in std::str::from_utf8(&dump).unwrap().lines() {
+            let slow = PasswordEntry::parse(line).unwrap();
+            let fast = PasswordEntry::parse_bytes(line.as_bytes()).unwrap();
+            for policy in policies.iter() {
+                assert_eq!(policy.policy.check(&slow), policy.policy.check(&fast), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn passes_agree() {
+        let policies = default_policies();
+        let database = "1-3 a: abcde\n\n1-3 b: cdefg\r\nnot an entry\n\n\n2-9 c: ccccccccc\n";
+        let mut expected = stream::Counts::new(policies.len());
+        stream::validate_chunk(database.as_bytes(), &policies, &mut expected);
+        assert_eq!(validate_lines(database.as_bytes(), &policies).unwrap(), expected);
+        assert_eq!(expected.malformed, 1);
+    }
+}
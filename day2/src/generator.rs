@@ -0,0 +1,246 @@
+use crate::PasswordEntry;
+use crate::requirement::Requirement;
+
+/// Small xorshift generator, so generated passwords and dumps can be reproduced from a seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Self(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random number in 0..n. n must not be zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Random number in min..=max.
+    pub fn between(&mut self, min: usize, max: usize) -> usize {
+        match ((max - min) as u64).checked_add(1) {
+            Some(span) => min + self.below(span) as usize,
+            None => min + self.next() as usize // the span covers all of u64
+        }
+    }
+}
+
+/// The two policies from the puzzle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Count,    // policy 1: the character occurs first to second times
+    Position  // policy 2: the character is at exactly one of the two positions
+}
+
+/// What a generated password should do to its policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Valid,
+    TooFew,          // count policy only
+    TooMany,         // count policy only
+    NeitherPosition, // position policy only
+    BothPositions    // position policy only
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "1" => Some(Policy::Count),
+            "2" => Some(Policy::Position),
+            _ => None
+        }
+    }
+}
+
+impl Outcome {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "valid" => Some(Outcome::Valid),
+            "too-few" => Some(Outcome::TooFew),
+            "too-many" => Some(Outcome::TooMany),
+            "neither" => Some(Outcome::NeitherPosition),
+            "both" => Some(Outcome::BothPositions),
+            _ => None
+        }
+    }
+}
+
+/// Splits a policy like "1-3 a" into its numbers and the required character.
+pub fn parse_policy_line(line: &str) -> Result<(usize, usize, char), String> {
+    let entry = format!("{}: ", line.trim());
+    let parsed = PasswordEntry::parse(&entry)?;
+    if !parsed.password.is_empty() {
+        return Err(format!("Policy \"{}\" has trailing characters", line));
+    }
+    match parsed.required {
+        Requirement::Char(c) => Ok((parsed.first, parsed.second, c)),
+        _ => Err(format!("Can only generate passwords for single characters, not {}", parsed.required))
+    }
+}
+
+/// What goes into one place of a generated password.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Required, // the required character
+    Other,    // anything but the required character
+    Any       // doesn't matter for the policy
+}
+
+/// Passwords are about as long as the larger policy number, so anything above this is refused
+///  instead of running out of memory.
+pub const MAX_POLICY_NUMBER : usize = 100_000;
+
+/// Generates a password for the policy "first-second required" that has the given outcome, or an
+///  error if no such password exists (e.g. too few occurrences when zero are allowed).
+pub fn generate(first: usize, second: usize, required: char, policy: Policy, outcome: Outcome, rng: &mut Rng) -> Result<String, String> {
+    if first.max(second) > MAX_POLICY_NUMBER {
+        return Err(format!("Policy \"{}-{} {}\" is too large, numbers can be at most {}", first, second, required, MAX_POLICY_NUMBER));
+    }
+    let slots = match (policy, outcome) {
+        (Policy::Count, Outcome::Valid) if first <= second => count_slots(rng.between(first, second), rng),
+        (Policy::Count, Outcome::TooFew) if first > 0 => count_slots(rng.between(0, first - 1), rng),
+        (Policy::Count, Outcome::TooMany) => count_slots(rng.between(second + 1, second + 4), rng),
+        (Policy::Position, Outcome::Valid) if first != second && first.max(second) > 0 => {
+            // pick the position that holds. position 0 never can, since positions are one-based
+            let holds = if first == 0 || (second != 0 && rng.below(2) == 0) { second } else { first };
+            position_slots(first, second, &[holds], rng)
+        },
+        (Policy::Position, Outcome::NeitherPosition) => position_slots(first, second, &[], rng),
+        (Policy::Position, Outcome::BothPositions) if first > 0 && second > 0 => position_slots(first, second, &[first, second], rng),
+        _ => return Err(format!("No password for \"{}-{} {}\" can be {:?} under the {:?} policy", first, second, required, outcome, policy))
+    };
+
+    let others : Vec<char> = ('a'..='z').filter(|c| *c != required).collect();
+    Ok(slots.into_iter().map(|slot| match slot {
+        Slot::Required => required,
+        Slot::Any if rng.below(4) == 0 => required,
+        Slot::Other | Slot::Any => others[rng.below(others.len() as u64) as usize]
+    }).collect())
+}
+
+/// Exactly count required characters at random places, plus a few others.
+fn count_slots(count: usize, rng: &mut Rng) -> Vec<Slot> {
+    let mut slots = vec![Slot::Required; count];
+    for _ in 0..rng.below(8) {
+        let at = rng.between(0, slots.len());
+        slots.insert(at, Slot::Other);
+    }
+    slots
+}
+
+/// The required character at the positions in holds, something else at the remaining policy
+///  positions, and anything everywhere else.
+fn position_slots(first: usize, second: usize, holds: &[usize], rng: &mut Rng) -> Vec<Slot> {
+    let length = first.max(second) + rng.below(5) as usize;
+    let mut slots = vec![Slot::Any; length];
+    for position in [first, second].iter() {
+        if *position > 0 {
+            slots[position - 1] = Slot::Other;
+        }
+    }
+    for position in holds {
+        slots[position - 1] = Slot::Required;
+    }
+    slots
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{POLICY_1, POLICY_2};
+    use crate::policy::{parse_policy, PasswordPolicy};
+
+    fn check(policy: &dyn PasswordPolicy, first: usize, second: usize, required: char, password: &str) -> bool {
+        policy.check(&PasswordEntry { first, second, required: Requirement::Char(required), password })
+    }
+
+    #[test]
+    fn round_trip() {
+        let policy_1 = parse_policy(POLICY_1).unwrap();
+        let policy_2 = parse_policy(POLICY_2).unwrap();
+        let mut rng = Rng::new(2020);
+
+        for _ in 0..2000 {
+            let first = rng.between(0, 12);
+            let second = rng.between(0, 12);
+            let required = if rng.below(5) == 0 { 'ü' } else { (b'a' + rng.below(26) as u8) as char };
+
+            for outcome in [Outcome::Valid, Outcome::TooFew, Outcome::TooMany].iter() {
+                if let Ok(password) = generate(first, second, required, Policy::Count, *outcome, &mut rng) {
+                    let count = password.chars().filter(|c| *c == required).count();
+                    let expected = *outcome == Outcome::Valid;
+                    assert_eq!(check(&*policy_1, first, second, required, &password), expected, "{}-{} {}: {}", first, second, required, password);
+                    match outcome {
+                        Outcome::TooFew => assert!(count < first),
+                        Outcome::TooMany => assert!(count > second),
+                        _ => {}
+                    }
+                }
+            }
+
+            for outcome in [Outcome::Valid, Outcome::NeitherPosition, Outcome::BothPositions].iter() {
+                if let Ok(password) = generate(first, second, required, Policy::Position, *outcome, &mut rng) {
+                    let expected = *outcome == Outcome::Valid;
+                    assert_eq!(check(&*policy_2, first, second, required, &password), expected, "{}-{} {}: {}", first, second, required, password);
+                    let at = |p: usize| p > 0 && password.chars().nth(p - 1) == Some(required);
+                    match outcome {
+                        Outcome::NeitherPosition => assert!(!at(first) && !at(second)),
+                        Outcome::BothPositions => assert!(at(first) && at(second)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_outcomes() {
+        let mut rng = Rng::new(1);
+        assert!(generate(0, 3, 'a', Policy::Count, Outcome::TooFew, &mut rng).is_err());
+        assert!(generate(4, 3, 'a', Policy::Count, Outcome::Valid, &mut rng).is_err());
+        assert!(generate(1, 3, 'a', Policy::Count, Outcome::BothPositions, &mut rng).is_err());
+        assert!(generate(2, 2, 'a', Policy::Position, Outcome::Valid, &mut rng).is_err());
+        assert!(generate(0, 0, 'a', Policy::Position, Outcome::Valid, &mut rng).is_err());
+        assert!(generate(1, 1000000000000, 'a', Policy::Count, Outcome::Valid, &mut rng).is_err());
+        assert!(generate(1, usize::MAX, 'a', Policy::Position, Outcome::NeitherPosition, &mut rng).is_err());
+        assert!(generate(usize::MAX, 1, 'a', Policy::Count, Outcome::TooFew, &mut rng).is_err());
+        assert!(generate(1, MAX_POLICY_NUMBER, 'a', Policy::Count, Outcome::TooMany, &mut rng).is_ok());
+        assert!(generate(0, 3, 'a', Policy::Position, Outcome::BothPositions, &mut rng).is_err());
+        assert!(generate(1, 3, 'a', Policy::Position, Outcome::TooMany, &mut rng).is_err());
+        assert!(generate(0, 3, 'a', Policy::Position, Outcome::Valid, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn full_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..100 {
+            let n = rng.between(usize::MAX - 1, usize::MAX);
+            assert!(n >= usize::MAX - 1);
+        }
+        rng.between(0, usize::MAX);
+        assert_eq!(rng.between(5, 5), 5);
+    }
+
+    #[test]
+    fn seeded() {
+        let a : Vec<String> = (0..5).map(|i| generate(1, 3, 'a', Policy::Count, Outcome::Valid, &mut Rng::new(i)).unwrap()).collect();
+        let b : Vec<String> = (0..5).map(|i| generate(1, 3, 'a', Policy::Count, Outcome::Valid, &mut Rng::new(i)).unwrap()).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn policy_lines() {
+        assert_eq!(parse_policy_line("1-3 a"), Ok((1, 3, 'a')));
+        assert_eq!(parse_policy_line(" 2-14 ü "), Ok((2, 14, 'ü')));
+        assert!(parse_policy_line("1-3 a: abc").is_err());
+        assert!(parse_policy_line("a-3 a").is_err());
+        assert!(parse_policy_line("1-3 ab").is_err());
+    }
+}
@@ -1,62 +1,240 @@
-
 #[macro_use]
 extern crate lazy_static;
 
 extern crate regex;
+extern crate unicode_segmentation;
+
+mod audit;
+mod bench;
+mod generator;
+mod policy;
+mod requirement;
+mod stream;
 
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
 use std::fs::File;
 
 use regex::Regex;
 
+use policy::NamedPolicy;
+use requirement::Requirement;
+
+// the two policies from the puzzle, written as rules
+const POLICY_1 : &str = "count $c in $1-$2";
+const POLICY_2 : &str = "at $1 is $c xor at $2 is $c";
+
 lazy_static! {
-    static ref PASSWORD_REGEX : Regex = Regex::new("(\\d+)-(\\d+) (.): (.*)").unwrap();
+    // the requirement is everything up to the first ": ", so old single-character entries parse as before
+    static ref PASSWORD_REGEX : Regex = Regex::new("^(\\d+)-(\\d+) (.+?): (.*)$").unwrap();
 }
 
-fn split_password_entry(entry: &str) -> (usize,usize,char,String) {
-    let matches = PASSWORD_REGEX.captures(&entry).unwrap();
+fn split_password_entry(entry: &str) -> Result<(usize,usize,Requirement<'_>,&str), String> {
+    let matches = PASSWORD_REGEX.captures(entry).ok_or("Entry does not look like \"min-max c: password\"")?;
 
-    let min = matches[1].parse::<usize>().unwrap();
-    let max = matches[2].parse::<usize>().unwrap();
+    let min = matches[1].parse::<usize>().map_err(|e| format!("Invalid number {}: {}", &matches[1], e))?;
+    let max = matches[2].parse::<usize>().map_err(|e| format!("Invalid number {}: {}", &matches[2], e))?;
 
-    let required_char = matches[3].chars().next().unwrap();
+    let required = matches.get(3).unwrap().as_str();
+    let required = Requirement::parse(required).map_err(|e| format!("Invalid requirement {}: {}", required, e))?;
 
-    (min, max, required_char, matches[4].to_owned())
+    Ok((min, max, required, matches.get(4).unwrap().as_str()))
 }
 
-fn check_policy_1(entry: &str) -> bool {
-    let (min, max, required, password) = split_password_entry(entry);
+/// One line of the password database, split into the policy part and the password.
+pub struct PasswordEntry<'a> {
+    pub first: usize,
+    pub second: usize,
+    pub required: Requirement<'a>,
+    pub password: &'a str
+}
 
-    let mut char_count = 0;
-    for c in password.chars() {
-        if c == required {
-            char_count += 1;
-        }
+impl<'a> PasswordEntry<'a> {
+    pub fn parse(entry: &'a str) -> Result<Self, String> {
+        let (first, second, required, password) = split_password_entry(entry)?;
+        Ok(Self {
+            first,
+            second,
+            required,
+            password
+        })
+    }
+
+    /// Parses an entry without the regex and without allocating. Much faster than parse() on
+    ///  big inputs, but stricter: the whole line has to be "min-max c: password".
+    pub fn parse_bytes(line: &'a [u8]) -> Result<Self, &'static str> {
+        let (first, rest) = parse_number(line)?;
+        let rest = rest.strip_prefix(b"-").ok_or("Expected - after the first number")?;
+        let (second, rest) = parse_number(rest)?;
+        let rest = rest.strip_prefix(b" ").ok_or("Expected a space after the second number")?;
+
+        // same as the regex: the requirement is not empty and ends at the first ": " after that
+        let colon = rest.windows(2)
+                        .skip(1)
+                        .position(|w| w == b": ")
+                        .ok_or("Expected \": \" after the requirement")? + 1;
+        let required = std::str::from_utf8(&rest[..colon]).map_err(|_| "Invalid UTF-8 in requirement")?;
+        let required = Requirement::parse(required)?;
+        let password = std::str::from_utf8(&rest[colon + 2..]).map_err(|_| "Invalid UTF-8 in password")?;
+
+        Ok(Self {
+            first,
+            second,
+            required,
+            password
+        })
     }
-    (char_count >= min) && (char_count <= max)
 }
 
-fn check_policy_2(entry: &str) -> bool {
-    let (pos1, pos2, required, password) = split_password_entry(entry);
+fn parse_number(s: &[u8]) -> Result<(usize, &[u8]), &'static str> {
+    let digits = s.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return Err("Expected a number");
+    }
+    let mut value : usize = 0;
+    for b in &s[..digits] {
+        value = value.checked_mul(10)
+                     .and_then(|v| v.checked_add((b - b'0') as usize))
+                     .ok_or("Number too large")?;
+    }
+    Ok((value, &s[digits..]))
+}
 
-    let mut match_count = 0;
-    for (index, c) in password.char_indices() {
-        let one_based_index = index+1;
-        if (one_based_index == pos1) || (one_based_index == pos2) {
-            if required == c {
-                match_count += 1;
+fn default_policies() -> Vec<NamedPolicy> {
+    vec![
+        NamedPolicy { name: "policy 1".to_string(), policy: policy::parse_policy(POLICY_1).unwrap() },
+        NamedPolicy { name: "policy 2".to_string(), policy: policy::parse_policy(POLICY_2).unwrap() }
+    ]
+}
+
+fn main() {
+
+    // usage: day2 [--audit csv|json] [--stream] [--input path|-] [policy file]
+    //        day2 --bench <dump file> [policy file]
+    //        day2 --generate <dump file> <megabytes>
+    //        day2 --passwords <policy, e.g. "1-3 a"> <1|2> <valid|too-few|too-many|neither|both> [count [seed]]
+    let mut audit_format = None;
+    let mut streaming = false;
+    let mut input_path = "day2/input.txt".to_string();
+    let mut bench_path = None;
+    let mut policy_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--audit" {
+            let format = args.next().expect("--audit needs a format (csv or json)");
+            audit_format = Some(audit::Format::parse(&format).expect("Audit format must be csv or json"));
+        }else if arg == "--stream" {
+            streaming = true;
+        }else if arg == "--input" {
+            input_path = args.next().expect("--input needs a path, or - for stdin");
+        }else if arg == "--bench" {
+            bench_path = Some(args.next().expect("--bench needs a dump file"));
+        }else if arg == "--generate" {
+            let path = args.next().expect("--generate needs a path");
+            let megabytes : usize = args.next().expect("--generate needs a size in megabytes")
+                                        .parse().expect("Size must be a number");
+            let mut out = std::io::BufWriter::new(File::create(&path).expect("Failed to create dump file"));
+            bench::generate_dump(&mut out, megabytes * 1_000_000, 2020).expect("Failed to write dump");
+            return;
+        }else if arg == "--passwords" {
+            let policy_line = args.next().expect("--passwords needs a policy like \"1-3 a\"");
+            let (first, second, required) = generator::parse_policy_line(&policy_line).unwrap_or_else(|e| panic!("Invalid policy: {}", e));
+            let policy = generator::Policy::parse(&args.next().unwrap_or_default()).expect("Policy must be 1 or 2");
+            let outcome = generator::Outcome::parse(&args.next().unwrap_or_default()).expect("Outcome must be valid, too-few, too-many, neither or both");
+            let count : usize = args.next().map(|c| c.parse().expect("Count must be a number")).unwrap_or(1);
+            let seed : u64 = args.next().map(|s| s.parse().expect("Seed must be a number")).unwrap_or(2020);
+            let mut rng = generator::Rng::new(seed);
+            for _ in 0..count {
+                let password = generator::generate(first, second, required, policy, outcome, &mut rng).unwrap_or_else(|e| panic!("{}", e));
+                println!("{}-{} {}: {}", first, second, required, password);
             }
+            return;
+        }else{
+            policy_path = Some(arg);
         }
     }
 
-    match_count == 1
+    let policies = match policy_path {
+        Some(path) => {
+            let policy_file = std::fs::read_to_string(&path).expect("Failed to open policy file");
+            policy::parse_policy_file(&policy_file).unwrap_or_else(|e| panic!("Invalid policy file {}: {}", path, e))
+        },
+        None => default_policies()
+    };
+
+    if let Some(path) = bench_path {
+        bench::run(&path, &policies).expect("Benchmark failed");
+        return;
+    }
+
+    let stdin = std::io::stdin();
+    let mut input : Box<dyn Read> = if input_path == "-" {
+        Box::new(stdin.lock())
+    }else{
+        Box::new(File::open(&input_path).expect("Failed to open input file"))
+    };
+
+    if streaming {
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let counts = stream::validate_parallel(&mut input, &policies, threads, 1 << 20).expect("Failed to read input");
+        if counts.malformed > 0 {
+            println!("Skipped {} malformed lines", counts.malformed);
+        }
+        println!("Out of {} passwords:", counts.entries);
+        for (policy, valid_count) in policies.iter().zip(counts.valid.iter()) {
+            println!("  {} are valid according to {}", valid_count, policy.name);
+        }
+        return;
+    }
+
+    let reader = BufReader::new(input);
+
+    if let Some(format) = audit_format {
+        let reports = audit::audit(reader, &policies).expect("Failed to read input");
+        let stdout = std::io::stdout();
+        audit::write_report(&reports, format, &mut stdout.lock()).expect("Failed to write report");
+        return;
+    }
+
+    let mut pw_count = 0;
+    let mut valid_counts = vec![0; policies.len()];
+
+    for (line_index, line_result) in reader.lines().enumerate() {
+        let line = line_result.expect("Failed to read line");
+        let entry = match PasswordEntry::parse(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!("Skipping line {}: {}", line_index + 1, e);
+                continue;
+            }
+        };
+        pw_count += 1;
+        for (policy, valid_count) in policies.iter().zip(valid_counts.iter_mut()) {
+            if policy.policy.check(&entry) {
+                *valid_count += 1;
+            }
+        }
+    }
+
+    println!("Out of {} passwords:", pw_count);
+    for (policy, valid_count) in policies.iter().zip(valid_counts.iter()) {
+        println!("  {} are valid according to {}", valid_count, policy.name);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn check_policy_1(entry: &str) -> bool {
+        policy::parse_policy(POLICY_1).unwrap().check(&PasswordEntry::parse(entry).unwrap())
+    }
+
+    fn check_policy_2(entry: &str) -> bool {
+        policy::parse_policy(POLICY_2).unwrap().check(&PasswordEntry::parse(entry).unwrap())
+    }
+
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn policy_1() {
         assert_eq!(check_policy_1("1-3 a: abcde"), true);
         assert_eq!(check_policy_1("1-3 b: cdefg"), false);
@@ -67,31 +245,51 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn policy_2() {
         assert_eq!(check_policy_2("1-3 a: abcde"), true);
         assert_eq!(check_policy_2("1-3 b: cdefg"), false);
         assert_eq!(check_policy_2("2-9 c: ccccccccc"), false);
+        assert_eq!(check_policy_2("1-2 a: ßa"), true);
+        assert_eq!(check_policy_2("1-3 a: ßa"), false);
+        assert_eq!(check_policy_2("2-4 ü: ßüðü"), false);
+        assert_eq!(check_policy_2("2-4 ü: ßüðu"), true);
     }
-}
 
-fn main() {
+    #[test]
+    fn multi_character_requirements() {
+        assert!(check_policy_1("1-3 ab: abxab"));
+        assert!(!check_policy_1("1-1 aa: aaa")); // occurrences overlap
+        assert!(check_policy_1("2-4 [0-9]: a1b22"));
+        assert!(!check_policy_1("2-4 [0-9]: 12345"));
+        assert!(check_policy_1("1-1 [^a-z]: abcD"));
+        assert!(check_policy_1("1-1 ü: : ü")); // the requirement ends at the first ": "
 
-    let mut pw_count = 0;
-    let mut policy1_valid_count = 0;
-    let mut policy2_valid_count = 0;
+        assert!(check_policy_2("1-3 ab: abcde"));
+        assert!(!check_policy_2("1-3 ab: ababa"));
+        assert!(!check_policy_2("2-3 aa: baaa"));
+        assert!(check_policy_2("1-4 [0-9]: 1abc"));
+        assert!(!check_policy_2("1-4 [0-9]: 1ab2"));
+        assert!(check_policy_2("1-2 :: x:"));
+    }
 
-    let f = File::open("day2/input.txt").expect("Failed to open input file");
-    let reader = BufReader::new(f);
-    for line_result in reader.lines() {
-        let line = line_result.expect("Failed to read line");
-        pw_count += 1;
-        if check_policy_1(&line) {
-            policy1_valid_count += 1;
-        }
-        if check_policy_2(&line) {
-            policy2_valid_count += 1;
+    #[test]
+    fn byte_parser() {
+        for line in ["1-3 a: abcde", "10-20 .: ....333....asf...g", "2-4 ü: ßüðu", "1-3 𝄞: 𝄞", "1-3 a: ",
+                     "1-3 ab: abab", "2-4 [0-9]: a1b2", "1-2 :: x", "1-2 : : x", "1-2 a: b: c"].iter() {
+            let slow = PasswordEntry::parse(line).unwrap();
+            let fast = PasswordEntry::parse_bytes(line.as_bytes()).unwrap();
+            assert_eq!((slow.first, slow.second, slow.required, slow.password),
+                       (fast.first, fast.second, fast.required, fast.password));
         }
-    }
 
-    println!("Out of {} passwords, {} are valid according to policy 1, {} are valid according to policy 2", pw_count, policy1_valid_count, policy2_valid_count);
+        assert!(PasswordEntry::parse_bytes(b"not an entry").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1-3 a abcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1- a: abcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1-3 : abcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1-3 []: abcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1-3 \xc3: abcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"1-3 a: ab\xffcde").is_err());
+        assert!(PasswordEntry::parse_bytes(b"99999999999999999999999-3 a: abcde").is_err());
+    }
 }
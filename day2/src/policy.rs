@@ -0,0 +1,623 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::PasswordEntry;
+use crate::requirement::Requirement;
+
+/// Something that can decide whether a database entry is valid.
+pub trait PasswordPolicy: Send + Sync {
+    fn check(&self, entry: &PasswordEntry) -> bool;
+
+    /// Describes what the policy requires of the entry, e.g. "count of 'a' in 1-3".
+    fn describe(&self, entry: &PasswordEntry) -> String;
+
+    /// Explains why the entry violates the policy. Only meaningful if check() returned false.
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        format!("{} does not hold", self.describe(entry))
+    }
+}
+
+/// A number in a rule. Can be fixed or taken from the entry's policy part ($1 and $2).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Literal(usize),
+    First,
+    Second
+}
+
+impl Number {
+    pub fn resolve(&self, entry: &PasswordEntry) -> usize {
+        match self {
+            Number::Literal(n) => *n,
+            Number::First => entry.first,
+            Number::Second => entry.second
+        }
+    }
+}
+
+/// A character in a rule. Can be fixed or whatever the entry requires ($c), which might also be a
+///  substring or a character class.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Letter {
+    Literal(char),
+    Required
+}
+
+impl Letter {
+    pub fn resolve<'a>(&self, entry: &PasswordEntry<'a>) -> Requirement<'a> {
+        match self {
+            Letter::Literal(c) => Requirement::Char(*c),
+            Letter::Required => entry.required
+        }
+    }
+}
+
+/// What counts as one "character" of a password when counting or looking at positions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Chars,
+    Graphemes // extended grapheme clusters, so "u" plus a combining diaeresis is one unit
+}
+
+impl Unit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Unit::Chars => "character",
+            Unit::Graphemes => "grapheme"
+        }
+    }
+
+    pub fn split<'a>(&self, password: &'a str) -> Units<'a> {
+        match self {
+            Unit::Chars => Units::Chars(password, password.char_indices()),
+            Unit::Graphemes => Units::Graphemes(password.graphemes(true))
+        }
+    }
+}
+
+/// Iterator over the units of a password. Doesn't allocate, so checking stays cheap.
+#[derive(Clone)]
+pub enum Units<'a> {
+    Chars(&'a str, std::str::CharIndices<'a>),
+    Graphemes(unicode_segmentation::Graphemes<'a>)
+}
+
+impl<'a> Iterator for Units<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            Units::Chars(password, indices) => indices.next().map(|(index, c)| &password[index..index + c.len_utf8()]),
+            Units::Graphemes(graphemes) => graphemes.next()
+        }
+    }
+}
+
+/// The letter has to appear between min and max times (inclusive). Overlapping occurrences of
+///  substrings all count.
+pub struct CountRange {
+    pub letter: Letter,
+    pub min: Number,
+    pub max: Number,
+    pub unit: Unit
+}
+
+impl CountRange {
+    fn count(&self, entry: &PasswordEntry) -> usize {
+        self.letter.resolve(entry).count(self.unit, entry.password)
+    }
+}
+
+impl PasswordPolicy for CountRange {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        let count = self.count(entry);
+        (count >= self.min.resolve(entry)) && (count <= self.max.resolve(entry))
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("count of {} in {}-{}", self.letter.resolve(entry), self.min.resolve(entry), self.max.resolve(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        format!("count of {} is {}, allowed {}-{}", self.letter.resolve(entry), self.count(entry), self.min.resolve(entry), self.max.resolve(entry))
+    }
+}
+
+/// The letter has to be at the given one-based position. Substrings have to start there.
+pub struct Position {
+    pub position: Number,
+    pub letter: Letter,
+    pub unit: Unit
+}
+
+impl PasswordPolicy for Position {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        self.letter.resolve(entry).matches_at(self.unit, entry.password, self.position.resolve(entry))
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("{} at position {} is {}", self.unit.name(), self.position.resolve(entry), self.letter.resolve(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        let position = self.position.resolve(entry);
+        let units : Vec<&str> = self.unit.split(entry.password).collect();
+        match position.checked_sub(1).and_then(|index| units.get(index)) {
+            Some(unit) => format!("{} at position {} is '{}', expected {}", self.unit.name(), position, unit, self.letter.resolve(entry)),
+            None => format!("no {} at position {} (length is {})", self.unit.name(), position, units.len())
+        }
+    }
+}
+
+/// None of the characters may appear in the password.
+pub struct Forbidden {
+    pub chars: String
+}
+
+impl PasswordPolicy for Forbidden {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        !entry.password.chars().any(|c| self.chars.contains(c))
+    }
+
+    fn describe(&self, _entry: &PasswordEntry) -> String {
+        format!("none of {:?}", self.chars)
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        match entry.password.chars().find(|c| self.chars.contains(*c)) {
+            Some(c) => format!("contains forbidden character {:?}", c),
+            None => self.describe(entry)
+        }
+    }
+}
+
+/// The password needs at least this many characters.
+pub struct MinLength {
+    pub length: Number,
+    pub unit: Unit
+}
+
+impl PasswordPolicy for MinLength {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        self.unit.split(entry.password).count() >= self.length.resolve(entry)
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("at least {} {}s", self.length.resolve(entry), self.unit.name())
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        format!("length is {} {}s, required at least {}", self.unit.split(entry.password).count(), self.unit.name(), self.length.resolve(entry))
+    }
+}
+
+pub struct And(pub Box<dyn PasswordPolicy>, pub Box<dyn PasswordPolicy>);
+pub struct Or(pub Box<dyn PasswordPolicy>, pub Box<dyn PasswordPolicy>);
+pub struct Xor(pub Box<dyn PasswordPolicy>, pub Box<dyn PasswordPolicy>);
+pub struct Not(pub Box<dyn PasswordPolicy>);
+
+impl PasswordPolicy for And {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        self.0.check(entry) && self.1.check(entry)
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("({} and {})", self.0.describe(entry), self.1.describe(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        // only report the parts that actually failed
+        let reasons : Vec<String> = [&self.0, &self.1].iter()
+                                                      .filter(|p| !p.check(entry))
+                                                      .map(|p| p.reason(entry))
+                                                      .collect();
+        reasons.join("; ")
+    }
+}
+
+impl PasswordPolicy for Or {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        self.0.check(entry) || self.1.check(entry)
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("({} or {})", self.0.describe(entry), self.1.describe(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        format!("{}; {}", self.0.reason(entry), self.1.reason(entry))
+    }
+}
+
+impl PasswordPolicy for Xor {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        self.0.check(entry) != self.1.check(entry)
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("({} xor {})", self.0.describe(entry), self.1.describe(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        if self.0.check(entry) {
+            format!("{} and {}, but only one may hold", self.0.describe(entry), self.1.describe(entry))
+        }else{
+            format!("{}; {}", self.0.reason(entry), self.1.reason(entry))
+        }
+    }
+}
+
+impl PasswordPolicy for Not {
+    fn check(&self, entry: &PasswordEntry) -> bool {
+        !self.0.check(entry)
+    }
+
+    fn describe(&self, entry: &PasswordEntry) -> String {
+        format!("not {}", self.0.describe(entry))
+    }
+
+    fn reason(&self, entry: &PasswordEntry) -> String {
+        format!("{}, but must not", self.0.describe(entry))
+    }
+}
+
+
+/// A policy with the name it was given in a policy file.
+pub struct NamedPolicy {
+    pub name: String,
+    pub policy: Box<dyn PasswordPolicy>
+}
+
+/// Parses a policy file. Every non-empty line that is not a comment (#) has the form
+///  "name: rule". See parse_policy() for the rule syntax.
+pub fn parse_policy_file(s: &str) -> Result<Vec<NamedPolicy>, String> {
+    let mut policies = Vec::new();
+    for (line_index, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap().trim();
+        let rule = parts.next().ok_or_else(|| format!("Line {}: Expected \"name: rule\"", line_index + 1))?;
+        let policy = parse_policy(rule).map_err(|e| format!("Line {}: {}", line_index + 1, e))?;
+        policies.push(NamedPolicy {
+            name: name.to_string(),
+            policy
+        });
+    }
+    Ok(policies)
+}
+
+/// Parses a single rule. Rules are made from these terms:
+///
+///   count L in N-N     the letter L appears N to N times
+///   at N is L          the letter at position N is L
+///   forbid "..."       none of the quoted characters appear
+///   length >= N        the password is at least N characters long
+///
+///  where N is a number, $1 or $2 (the numbers from the entry) and L is a quoted character like
+///  'a' or $c (what the entry requires: a character, a substring or a class like [0-9]). Terms
+///  can be combined with not, and, xor and or (binding in that order) and grouped with
+///  parentheses.
+///
+///  Counts, positions and lengths are in characters (not bytes). Wrapping terms in
+///  graphemes(...) makes them use grapheme clusters instead.
+pub fn parse_policy(s: &str) -> Result<Box<dyn PasswordPolicy>, String> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        unit: Unit::Chars
+    };
+    let policy = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(policy),
+        Some(t) => Err(format!("Unexpected {:?} after end of rule", t))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(usize),
+    Param(char),
+    Letter(char),
+    Text(String),
+    Dash,
+    AtLeast,
+    Open,
+    Close
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {},
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '-' => tokens.push(Token::Dash),
+            '>' => {
+                if chars.next() != Some('=') {
+                    return Err("Expected >=".to_string());
+                }
+                tokens.push(Token::AtLeast);
+            },
+            '$' => match chars.next() {
+                Some(p) if p == '1' || p == '2' || p == 'c' => tokens.push(Token::Param(p)),
+                _ => return Err("Expected $1, $2 or $c".to_string())
+            },
+            '\'' => {
+                let letter = chars.next().ok_or("Unterminated character")?;
+                if chars.next() != Some('\'') {
+                    return Err("Character literals must contain exactly one character".to_string());
+                }
+                tokens.push(Token::Letter(letter));
+            },
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err("Unterminated string".to_string())
+                    }
+                }
+                tokens.push(Token::Text(text));
+            },
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(*d);
+                    chars.next();
+                }
+                tokens.push(Token::Number(digits.parse().map_err(|_| format!("Number too large: {}", digits))?));
+            },
+            c if c.is_alphabetic() => {
+                let mut word = c.to_string();
+                while let Some(d) = chars.peek().filter(|d| d.is_alphanumeric() || **d == '_') {
+                    word.push(*d);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            },
+            _ => return Err(format!("Unexpected character {}", c))
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    unit: Unit // for the terms currently being parsed
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.next).cloned().ok_or("Unexpected end of rule")?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        let token = self.take()?;
+        if token == expected {
+            Ok(())
+        }else{
+            Err(format!("Expected {:?}, found {:?}", expected, token))
+        }
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn parse_or(&mut self) -> Result<Box<dyn PasswordPolicy>, String> {
+        let mut left = self.parse_xor()?;
+        while self.is_word("or") {
+            self.next += 1;
+            left = Box::new(Or(left, self.parse_xor()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_xor(&mut self) -> Result<Box<dyn PasswordPolicy>, String> {
+        let mut left = self.parse_and()?;
+        while self.is_word("xor") {
+            self.next += 1;
+            left = Box::new(Xor(left, self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Box<dyn PasswordPolicy>, String> {
+        let mut left = self.parse_unary()?;
+        while self.is_word("and") {
+            self.next += 1;
+            left = Box::new(And(left, self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Box<dyn PasswordPolicy>, String> {
+        match self.take()? {
+            Token::Open => {
+                let inner = self.parse_or()?;
+                self.expect(Token::Close)?;
+                Ok(inner)
+            },
+            Token::Word(w) => match w.as_str() {
+                "not" => Ok(Box::new(Not(self.parse_unary()?))),
+                "graphemes" => {
+                    self.expect(Token::Open)?;
+                    let outer_unit = self.unit;
+                    self.unit = Unit::Graphemes;
+                    let inner = self.parse_or();
+                    self.unit = outer_unit;
+                    let inner = inner?;
+                    self.expect(Token::Close)?;
+                    Ok(inner)
+                },
+                "count" => {
+                    let letter = self.parse_letter()?;
+                    self.expect(Token::Word("in".to_string()))?;
+                    let min = self.parse_number()?;
+                    self.expect(Token::Dash)?;
+                    let max = self.parse_number()?;
+                    Ok(Box::new(CountRange { letter, min, max, unit: self.unit }))
+                },
+                "at" => {
+                    let position = self.parse_number()?;
+                    self.expect(Token::Word("is".to_string()))?;
+                    let letter = self.parse_letter()?;
+                    Ok(Box::new(Position { position, letter, unit: self.unit }))
+                },
+                "forbid" => match self.take()? {
+                    Token::Text(chars) => Ok(Box::new(Forbidden { chars })),
+                    t => Err(format!("Expected quoted characters after forbid, found {:?}", t))
+                },
+                "length" => {
+                    self.expect(Token::AtLeast)?;
+                    let length = self.parse_number()?;
+                    Ok(Box::new(MinLength { length, unit: self.unit }))
+                },
+                _ => Err(format!("Unknown rule {}", w))
+            },
+            t => Err(format!("Expected a rule, found {:?}", t))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Number, String> {
+        match self.take()? {
+            Token::Number(n) => Ok(Number::Literal(n)),
+            Token::Param('1') => Ok(Number::First),
+            Token::Param('2') => Ok(Number::Second),
+            t => Err(format!("Expected a number, $1 or $2, found {:?}", t))
+        }
+    }
+
+    fn parse_letter(&mut self) -> Result<Letter, String> {
+        match self.take()? {
+            Token::Letter(c) => Ok(Letter::Literal(c)),
+            Token::Param('c') => Ok(Letter::Required),
+            t => Err(format!("Expected a quoted character or $c, found {:?}", t))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(rule: &str, entry: &str) -> bool {
+        parse_policy(rule).unwrap().check(&PasswordEntry::parse(entry).unwrap())
+    }
+
+    fn reason(rule: &str, entry: &str) -> String {
+        let entry = PasswordEntry::parse(entry).unwrap();
+        let policy = parse_policy(rule).unwrap();
+        assert!(!policy.check(&entry));
+        policy.reason(&entry)
+    }
+
+    #[test]
+    fn rules() {
+        assert!(check("count 'x' in 1-2", "1-3 a: axbx"));
+        assert!(!check("count 'x' in 3-$2", "1-3 a: axbx"));
+        assert!(check("count $c in 0-0", "1-3 a: xbx"));
+        assert!(check("at 2 is 'x'", "1-3 a: axbx"));
+        assert!(!check("at $1 is $c", "1-3 a: xbx"));
+        assert!(check("forbid \"!?\"", "1-3 a: abc"));
+        assert!(!check("forbid \"!?\"", "1-3 a: ab?c"));
+        assert!(check("length >= 3", "1-3 a: abc"));
+        assert!(!check("length >= $2", "1-4 a: abc"));
+    }
+
+    #[test]
+    fn combinators() {
+        assert!(check("at 1 is 'a' xor at 2 is 'a'", "1-3 a: ab"));
+        assert!(!check("at 1 is 'a' xor at 2 is 'a'", "1-3 a: aa"));
+        assert!(check("at 1 is 'a' or at 2 is 'a'", "1-3 a: aa"));
+        assert!(!check("at 1 is 'a' and not at 2 is 'a'", "1-3 a: aa"));
+        // and binds tighter than xor, xor tighter than or
+        assert!(check("at 1 is 'x' and at 1 is 'y' or length >= 1", "1-3 a: aa"));
+        assert!(!check("at 1 is 'x' and (at 1 is 'y' or length >= 1)", "1-3 a: aa"));
+        assert!(check("length >= 1 xor length >= 1 or length >= 1", "1-3 a: aa"));
+        assert!(!check("length >= 1 xor (length >= 1 or length >= 1)", "1-3 a: aa"));
+    }
+
+    #[test]
+    fn multibyte_positions() {
+        // positions are characters, not byte offsets
+        assert!(check("at 2 is 'a'", "1-3 a: ßa"));
+        assert!(check("at $1 is $c xor at $2 is $c", "1-2 a: ßa"));
+        assert!(check("at 3 is 'ü'", "1-3 a: ßðüa"));
+        assert!(!check("at 5 is 'ü'", "1-3 a: ßðüa"));
+        assert!(!check("at 0 is 'ß'", "1-3 a: ßðüa"));
+        assert!(check("at 1 is 'ß' and length >= 4 and not length >= 5", "1-3 a: ßðüa"));
+
+        // both policies agree on what the second character is
+        assert!(check("count $c in 1-1 and at 2 is $c", "1-3 ü: ßü"));
+    }
+
+    #[test]
+    fn grapheme_positions() {
+        // "u" followed by a combining diaeresis is two characters but one grapheme
+        let entry = "1-3 a: u\u{0308}a";
+        assert!(check("at 3 is 'a'", entry));
+        assert!(check("graphemes(at 2 is 'a')", entry));
+        assert!(!check("graphemes(at 3 is 'a')", entry));
+        assert!(check("length >= 3 and graphemes(not length >= 3)", entry));
+        assert!(check("count 'u' in 1-1", entry));
+        assert!(check("graphemes(count 'u' in 0-0)", entry));
+        assert!(check("graphemes(at 1 is 'ß' and (length >= 1 or at 1 is 'x'))", "1-3 a: ßa"));
+    }
+
+    #[test]
+    fn failure_reasons() {
+        assert_eq!(reason("count $c in $1-$2", "1-3 a: aaaaa"), "count of 'a' is 5, allowed 1-3");
+        assert_eq!(reason("at $1 is $c", "2-3 a: ßü"), "character at position 2 is 'ü', expected 'a'");
+        assert_eq!(reason("at 5 is $c", "2-3 a: ßü"), "no character at position 5 (length is 2)");
+        assert_eq!(reason("graphemes(at 0 is $c)", "2-3 a: ßü"), "no grapheme at position 0 (length is 2)");
+        assert_eq!(reason("forbid \"?!\"", "2-3 a: ab!?"), "contains forbidden character '!'");
+        assert_eq!(reason("length >= 8", "2-3 a: abc"), "length is 3 characters, required at least 8");
+
+        assert_eq!(reason("length >= 1 and length >= 8", "2-3 a: abc"), "length is 3 characters, required at least 8");
+        assert_eq!(reason("at $1 is $c xor at $2 is $c", "1-2 a: aa"), "character at position 1 is 'a' and character at position 2 is 'a', but only one may hold");
+        assert_eq!(reason("at $1 is $c xor at $2 is $c", "1-2 a: bb"), "character at position 1 is 'b', expected 'a'; character at position 2 is 'b', expected 'a'");
+        assert_eq!(reason("not (length >= 1 or at 1 is 'x')", "1-2 a: bb"), "(at least 1 characters or character at position 1 is 'x'), but must not");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(parse_policy("count 'ab' in 1-2").is_err());
+        assert!(parse_policy("count $c in 1").is_err());
+        assert!(parse_policy("at $3 is $c").is_err());
+        assert!(parse_policy("length > 3").is_err());
+        assert!(parse_policy("forbid \"abc").is_err());
+        assert!(parse_policy("(length >= 3").is_err());
+        assert!(parse_policy("length >= 3 length >= 4").is_err());
+        assert!(parse_policy("sparkle $c").is_err());
+        assert!(parse_policy("graphemes at 1 is $c").is_err());
+        assert!(parse_policy("graphemes(at 1 is $c").is_err());
+    }
+
+    #[test]
+    fn policy_file() {
+        let file = "# comment\n\nfirst: count $c in $1-$2\n  second : at $1 is $c xor at $2 is $c\n";
+        let policies = parse_policy_file(file).unwrap();
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0].name, "first");
+        assert_eq!(policies[1].name, "second");
+        assert!(policies[1].policy.check(&PasswordEntry::parse("1-3 a: abcde").unwrap()));
+
+        let error = parse_policy_file("first: count $c in $1-$2\nbroken\n").err().unwrap();
+        assert!(error.starts_with("Line 2"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use crate::policy::{Unit, Units};
+
+/// What the policy part of an entry asks for: a single character ("1-3 a"), a substring
+///  ("1-3 ab") or a character class ("1-3 [0-9]").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Requirement<'a> {
+    Char(char),
+    Substring(&'a str),
+    Class(CharClass<'a>)
+}
+
+impl<'a> Requirement<'a> {
+    /// Classifies the text between the numbers and the colon of an entry. Anything in brackets
+    ///  is a class, a single character stays a character and everything else is a substring.
+    pub fn parse(s: &'a str) -> Result<Self, &'static str> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => Err("Empty requirement"),
+            (Some(c), None) => Ok(Requirement::Char(c)),
+            _ if s.starts_with('[') && s.ends_with(']') => CharClass::parse(&s[1..s.len() - 1]).map(Requirement::Class),
+            _ => Ok(Requirement::Substring(s))
+        }
+    }
+
+    /// Whether the units starting here match. Characters and classes match a single unit,
+    ///  substrings match as many units as they are made of, so occurrences may overlap.
+    pub fn matches_start(&self, unit: Unit, mut units: Units) -> bool {
+        match self {
+            Requirement::Char(c) => units.next().is_some_and(|u| single_char(u) == Some(*c)),
+            Requirement::Class(class) => units.next().and_then(single_char).is_some_and(|c| class.contains(c)),
+            Requirement::Substring(s) => unit.split(s).all(|expected| units.next() == Some(expected))
+        }
+    }
+
+    /// Number of places in the password where the requirement matches.
+    pub fn count(&self, unit: Unit, password: &str) -> usize {
+        let mut units = unit.split(password);
+        let mut count = 0;
+        loop {
+            if self.matches_start(unit, units.clone()) {
+                count += 1;
+            }
+            if units.next().is_none() {
+                return count;
+            }
+        }
+    }
+
+    /// Whether the requirement matches at the one-based position.
+    pub fn matches_at(&self, unit: Unit, password: &str, position: usize) -> bool {
+        let index = match position.checked_sub(1) {
+            Some(index) => index,
+            None => return false
+        };
+        let mut units = unit.split(password);
+        (&mut units).take(index).count() == index && self.matches_start(unit, units)
+    }
+}
+
+impl fmt::Display for Requirement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Char(c) => write!(f, "{:?}", c),
+            Requirement::Substring(s) => write!(f, "{:?}", s),
+            Requirement::Class(class) => write!(f, "{}", class)
+        }
+    }
+}
+
+fn single_char(unit: &str) -> Option<char> {
+    let mut chars = unit.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None
+    }
+}
+
+/// A character class like [a-z0-9_]. A leading ^ negates it, a - between two characters is a
+///  range and a - at either end is just a dash. Keeps the text of the class instead of building
+///  a set, so parsing an entry doesn't allocate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharClass<'a> {
+    pub negated: bool,
+    pub members: &'a str
+}
+
+impl<'a> CharClass<'a> {
+    /// Parses the text between the brackets.
+    pub fn parse(s: &'a str) -> Result<Self, &'static str> {
+        let (negated, members) = match s.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, s)
+        };
+        if members.is_empty() {
+            return Err("Empty character class");
+        }
+        let class = Self { negated, members };
+        if class.ranges().any(|(from, to)| from > to) {
+            return Err("Character range is out of order");
+        }
+        Ok(class)
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges().any(|(from, to)| from <= c && c <= to) != self.negated
+    }
+
+    /// The members as inclusive ranges. Single characters are ranges of one.
+    fn ranges(&self) -> impl Iterator<Item=(char, char)> + 'a {
+        let mut chars = self.members.chars();
+        std::iter::from_fn(move || {
+            let from = chars.next()?;
+            let mut ahead = chars.clone();
+            if ahead.next() == Some('-') {
+                if let Some(to) = ahead.next() {
+                    chars = ahead;
+                    return Some((from, to));
+                }
+            }
+            Some((from, from))
+        })
+    }
+}
+
+impl fmt::Display for CharClass<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}{}]", if self.negated { "^" } else { "" }, self.members)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Requirement::parse("a"), Ok(Requirement::Char('a')));
+        assert_eq!(Requirement::parse("["), Ok(Requirement::Char('[')));
+        assert_eq!(Requirement::parse("ab"), Ok(Requirement::Substring("ab")));
+        assert_eq!(Requirement::parse("[0-9]"), Ok(Requirement::Class(CharClass { negated: false, members: "0-9" })));
+        assert_eq!(Requirement::parse("[^ab]"), Ok(Requirement::Class(CharClass { negated: true, members: "ab" })));
+        assert!(Requirement::parse("").is_err());
+        assert!(Requirement::parse("[]").is_err());
+        assert!(Requirement::parse("[^]").is_err());
+        assert!(Requirement::parse("[9-0]").is_err());
+    }
+
+    #[test]
+    fn classes() {
+        let class = CharClass::parse("a-cx-").unwrap();
+        assert!(class.contains('a') && class.contains('b') && class.contains('c'));
+        assert!(class.contains('x') && class.contains('-'));
+        assert!(!class.contains('d') && !class.contains('y'));
+
+        let class = CharClass::parse("^0-9").unwrap();
+        assert!(class.contains('a'));
+        assert!(!class.contains('5'));
+
+        let class = CharClass::parse("-ä-ö").unwrap();
+        assert!(class.contains('-') && class.contains('ö'));
+        assert!(!class.contains('a'));
+    }
+
+    #[test]
+    fn counting() {
+        let count = |requirement: &str, password: &str| Requirement::parse(requirement).unwrap().count(Unit::Chars, password);
+        assert_eq!(count("a", "banana"), 3);
+        assert_eq!(count("ana", "banana"), 2); // overlapping
+        assert_eq!(count("aa", "aaaa"), 3);
+        assert_eq!(count("[an]", "banana"), 5);
+        assert_eq!(count("[^an]", "banana"), 1);
+        assert_eq!(count("üß", "üßüßü"), 2);
+        assert_eq!(count("xy", ""), 0);
+
+        // in graphemes, "u" followed by a combining diaeresis is not a "u"
+        assert_eq!(Requirement::parse("u").unwrap().count(Unit::Graphemes, "u\u{0308}u"), 1);
+        assert_eq!(Requirement::parse("uu").unwrap().count(Unit::Graphemes, "u\u{0308}uu"), 1);
+    }
+
+    #[test]
+    fn positions() {
+        let at = |requirement: &str, password: &str, position: usize| Requirement::parse(requirement).unwrap().matches_at(Unit::Chars, password, position);
+        assert!(at("ab", "xaby", 2));
+        assert!(!at("ab", "xaby", 3));
+        assert!(!at("by", "xaby", 4));
+        assert!(at("[0-9]", "a1", 2));
+        assert!(!at("[0-9]", "a1", 3));
+        assert!(!at("a", "a", 0));
+    }
+}
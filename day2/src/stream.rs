@@ -0,0 +1,185 @@
+use std::io::{self, Read};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::PasswordEntry;
+use crate::policy::NamedPolicy;
+
+/// Tally of a validation run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counts {
+    pub entries: usize,
+    pub malformed: usize,
+    pub valid: Vec<usize> // per policy
+}
+
+impl Counts {
+    pub fn new(policy_count: usize) -> Self {
+        Self {
+            entries: 0,
+            malformed: 0,
+            valid: vec![0; policy_count]
+        }
+    }
+
+    pub fn add(&mut self, other: &Counts) {
+        self.entries += other.entries;
+        self.malformed += other.malformed;
+        for (v, o) in self.valid.iter_mut().zip(other.valid.iter()) {
+            *v += *o;
+        }
+    }
+}
+
+/// Validates all lines in the chunk. The chunk must only contain whole lines. Empty lines are
+///  skipped.
+pub fn validate_chunk(chunk: &[u8], policies: &[NamedPolicy], counts: &mut Counts) {
+    for line in chunk.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        match PasswordEntry::parse_bytes(line) {
+            Ok(entry) => {
+                counts.entries += 1;
+                for (policy, valid) in policies.iter().zip(counts.valid.iter_mut()) {
+                    if policy.policy.check(&entry) {
+                        *valid += 1;
+                    }
+                }
+            },
+            Err(_) => counts.malformed += 1
+        }
+    }
+}
+
+/// Validates everything the reader provides. The input is read on the calling thread in chunks
+///  of roughly chunk_size bytes, which are then validated by the worker threads. Chunk buffers
+///  are handed back to the reader once done, so memory use stays at a few chunks per thread.
+pub fn validate_parallel<R: Read>(reader: &mut R, policies: &[NamedPolicy], threads: usize, chunk_size: usize) -> io::Result<Counts> {
+    let threads = threads.max(1);
+    let (work_tx, work_rx) = mpsc::sync_channel::<Vec<u8>>(threads * 2);
+    let work_rx = Mutex::new(work_rx);
+    let (free_tx, free_rx) = mpsc::channel::<Vec<u8>>();
+
+    thread::scope(|scope| {
+        let workers : Vec<_> = (0..threads).map(|_| {
+            let work_rx = &work_rx;
+            let free_tx = free_tx.clone();
+            scope.spawn(move || {
+                let mut counts = Counts::new(policies.len());
+                loop {
+                    let chunk = match work_rx.lock().unwrap().recv() {
+                        Ok(chunk) => chunk,
+                        Err(_) => break // reader is done
+                    };
+                    validate_chunk(&chunk, policies, &mut counts);
+                    // the reader might have stopped already, in which case we just drop the buffer
+                    let _ = free_tx.send(chunk);
+                }
+                counts
+            })
+        }).collect();
+        drop(free_tx);
+
+        let read_result = read_chunks(reader, chunk_size, &free_rx, |chunk| {
+            work_tx.send(chunk).expect("Validation workers stopped unexpectedly");
+        });
+        drop(work_tx);
+
+        let mut total = Counts::new(policies.len());
+        for worker in workers {
+            total.add(&worker.join().expect("Validation worker panicked"));
+        }
+        read_result.map(|_| total)
+    })
+}
+
+/// Splits the input into chunks ending on a line break and passes them to send. Buffers are
+///  taken from free whenever possible.
+fn read_chunks<R: Read, F: FnMut(Vec<u8>)>(reader: &mut R, chunk_size: usize, free: &mpsc::Receiver<Vec<u8>>, mut send: F) -> io::Result<()> {
+    let mut carry : Vec<u8> = Vec::new();
+    loop {
+        let mut buffer = free.try_recv().unwrap_or_else(|_| Vec::with_capacity(chunk_size));
+        buffer.clear();
+        buffer.extend_from_slice(&carry);
+        carry.clear();
+
+        // fill the buffer. whatever was left over from the last chunk comes first
+        let target = buffer.len() + chunk_size;
+        let mut eof = false;
+        while buffer.len() < target {
+            let old_len = buffer.len();
+            buffer.resize(target, 0);
+            match reader.read(&mut buffer[old_len..]) {
+                Ok(0) => {
+                    buffer.truncate(old_len);
+                    eof = true;
+                    break;
+                },
+                Ok(n) => buffer.truncate(old_len + n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => buffer.truncate(old_len),
+                Err(e) => return Err(e)
+            }
+        }
+
+        if eof {
+            if !buffer.is_empty() {
+                send(buffer);
+            }
+            return Ok(());
+        }
+
+        // the last line is probably incomplete, so keep it for the next chunk. if there is no
+        //  line break at all, the line is longer than a chunk and we need to keep reading
+        match buffer.iter().rposition(|b| *b == b'\n') {
+            Some(last_break) => {
+                carry.extend_from_slice(&buffer[last_break + 1..]);
+                buffer.truncate(last_break + 1);
+                send(buffer);
+            },
+            None => carry = buffer
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_policies;
+
+    const DATABASE : &str = "1-3 a: abcde\n1-3 b: cdefg\r\nnot an entry\n\n2-9 c: ccccccccc\n2-4 ü: ßßßuuüasð--Üüaaa";
+
+    #[test]
+    fn chunk_validation() {
+        let mut counts = Counts::new(2);
+        validate_chunk(DATABASE.as_bytes(), &default_policies(), &mut counts);
+        assert_eq!(counts, Counts { entries: 4, malformed: 1, valid: vec![3, 1] });
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let policies = default_policies();
+        let mut expected = Counts::new(2);
+        validate_chunk(DATABASE.as_bytes(), &policies, &mut expected);
+
+        // tiny chunks make sure lines get split across chunk boundaries
+        for chunk_size in [1, 2, 3, 7, 16, 1024].iter() {
+            for threads in [1, 3].iter() {
+                let counts = validate_parallel(&mut DATABASE.as_bytes(), &policies, *threads, *chunk_size).unwrap();
+                assert_eq!(counts, expected, "chunk size {}, {} threads", chunk_size, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let policies = default_policies();
+        let counts = validate_parallel(&mut input.as_bytes(), &policies, 4, 4096).unwrap();
+        assert_eq!(counts.entries, 1000);
+        assert_eq!(counts.malformed, 0);
+        assert_eq!(counts.valid, vec![603, 404]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::convert::TryInto;
+use std::time::Instant;
+
+use crate::{count_trees_many, modulus, Map};
+
+/// Pseudo-random bits for the given index, so generated maps can be reproduced from a seed. Each
+///  index is hashed on its own (splitmix64 finalizer), so no generator state is needed.
+pub fn random_bits(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A random map where roughly the given percentage of cells are trees.
+pub fn generate_map(width: usize, height: usize, tree_percent: u64, seed: u64) -> Map {
+    Map::from_fn(width, height, |x, y| random_bits(seed, (x + y*width) as u64) % 100 < tree_percent)
+}
+
+/// The map as it was stored before rows were packed into words, one bool per cell.
+struct BoolMap {
+    width: usize,
+    height: usize,
+    points: Vec<bool>
+}
+
+impl BoolMap {
+    fn new(map: &Map) -> Self {
+        Self {
+            width: map.width,
+            height: map.height,
+            points: (0..map.height).flat_map(|y| (0..map.width).map(move |x| map.tree_at(x, y))).collect()
+        }
+    }
+
+    /// The original count_trees(), minus the printing.
+    fn count_trees(&self, slope_x: isize, slope_y: isize) -> usize {
+        let w : isize = self.width.try_into().unwrap();
+        let height : isize = self.height.try_into().unwrap();
+        let mut x : isize = 0;
+        let mut y : isize = 0;
+        let mut trees_encountered = 0;
+        while (y >= 0) && (y < height) {
+            let offset : usize = (modulus(x, w) + y*w).try_into().unwrap();
+            if self.points[offset] {
+                trees_encountered += 1;
+            }
+            x += slope_x;
+            y += slope_y;
+        }
+        trees_encountered
+    }
+}
+
+/// Counts trees on a generated map for the slopes dx in -32..32, dy in 1..=4, once slope by slope
+///  on the old Vec<bool> map and once with count_trees_many(), and prints how long each took.
+pub fn run(width: usize, height: usize) {
+    let start = Instant::now();
+    let map = generate_map(width, height, 25, 2020);
+    let bool_map = BoolMap::new(&map);
+    println!("Generated {}x{} map in {:.3} s", width, height, start.elapsed().as_secs_f64());
+
+    let slopes : Vec<(isize,isize)> = (1..=4).flat_map(|y| (-32..32).map(move |x| (x, y))).collect();
+
+    let start = Instant::now();
+    let one_by_one : Vec<usize> = slopes.iter().map(|(x, y)| bool_map.count_trees(*x, *y)).collect();
+    let one_by_one_time = start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    let many = count_trees_many(&map, &slopes).unwrap();
+    let many_time = start.elapsed().as_secs_f64();
+
+    assert_eq!(one_by_one, many, "Both ways of counting must agree");
+    println!("{} slopes, {} trees in total", slopes.len(), many.iter().sum::<usize>());
+    println!("  Vec<bool>, slope by slope: {:>8.3} s", one_by_one_time);
+    println!("  count_trees_many:          {:>8.3} s ({:.1}x faster)", many_time, one_by_one_time / many_time);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count_trees;
+
+    #[test]
+    fn bool_map_baseline() {
+        let map = generate_map(70, 90, 30, 3);
+        let bool_map = BoolMap::new(&map);
+        for (x, y) in [(3, 1), (-5, 2), (0, 1), (64, 3), (1, -1)].iter() {
+            assert_eq!(bool_map.count_trees(*x, *y), count_trees(&map, *x, *y));
+        }
+    }
+}
@@ -1,34 +1,283 @@
 
+mod bench;
+mod render;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::convert::TryInto;
-use std::ops::Index;
+use std::ops::{Index, RangeInclusive};
+
+/// What happens when looking at a cell outside the map along one axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Boundary {
+    Wrap,  // the map repeats
+    Clamp, // the edge of the map continues forever
+    Empty, // there is only open ground outside the map
+    Error  // looking outside is an error
+}
+
+impl Boundary {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "wrap" => Some(Boundary::Wrap),
+            "clamp" => Some(Boundary::Clamp),
+            "empty" => Some(Boundary::Empty),
+            "error" => Some(Boundary::Error),
+            _ => None
+        }
+    }
+
+    /// Maps the coordinate into 0..size. None means the cell is outside and empty.
+    fn resolve(&self, coordinate: isize, size: isize) -> Result<Option<isize>, ()> {
+        if (coordinate >= 0) && (coordinate < size) {
+            return Ok(Some(coordinate));
+        }
+        match self {
+            Boundary::Wrap => Ok(Some(modulus(coordinate, size))),
+            Boundary::Clamp => Ok(Some(coordinate.clamp(0, size - 1))),
+            Boundary::Empty => Ok(None),
+            Boundary::Error => Err(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OutOfBounds {
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize
+}
+
+impl std::fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Map index out of bounds. Requested {}/{}, but size is {}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// What a cell of the map is covered with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Terrain {
+    Open,
+    Tree,
+    Rock,
+    Ice,
+    Snow
+}
+
+impl Terrain {
+    const ALL : [Terrain; 5] = [Terrain::Open, Terrain::Tree, Terrain::Rock, Terrain::Ice, Terrain::Snow];
+
+    fn from_glyph(c: char) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|t| t.glyph() == c)
+    }
+
+    fn glyph(&self) -> char {
+        match self {
+            Terrain::Open => '.',
+            Terrain::Tree => '#',
+            Terrain::Rock => '^',
+            Terrain::Ice => '~',
+            Terrain::Snow => '*'
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Terrain::Open => "open",
+            Terrain::Tree => "tree",
+            Terrain::Rock => "rock",
+            Terrain::Ice => "ice",
+            Terrain::Snow => "snow"
+        }
+    }
+}
+
+/// What it costs to pass a cell of each terrain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CostModel {
+    costs: [u64; 5] // in the order of Terrain::ALL
+}
+
+impl CostModel {
+    /// Trees cost 1 and everything else is free, so the cost of a path is its number of trees.
+    fn trees() -> Self {
+        Self { costs: [0, 1, 0, 0, 0] }
+    }
+
+    fn cost(&self, terrain: Terrain) -> u64 {
+        self.costs[terrain as usize]
+    }
+
+    /// Parses costs like "tree=1,rock=10,snow=2". Terrains that aren't mentioned keep their cost
+    ///  from trees().
+    fn parse(s: &str) -> Result<Self, String> {
+        let mut model = Self::trees();
+        for part in s.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            let (name, cost) = part.split_once('=').ok_or_else(|| format!("Expected \"terrain=cost\", found {}", part))?;
+            let terrain = Terrain::ALL.iter().find(|t| t.name() == name.trim()).ok_or_else(|| format!("Unknown terrain {}", name))?;
+            model.costs[*terrain as usize] = cost.trim().parse().map_err(|e| format!("Invalid cost for {}: {}", name, e))?;
+        }
+        Ok(model)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ParseErrorKind {
+    Empty,
+    Ragged { expected: usize, found: usize }, // row lengths in cells
+    UnknownGlyph(char)
+}
+
+/// Where and why a map couldn't be parsed. Lines and columns are one-based, columns count
+///  characters.
+#[derive(Debug, Clone, PartialEq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::Empty => write!(f, "Map has no rows"),
+            ParseErrorKind::Ragged { expected, found } => write!(f, "Row has {} cells, but the first row has {}", found, expected),
+            ParseErrorKind::UnknownGlyph(c) => write!(f, "Unknown glyph {:?}", c)
+        }
+    }
+}
 
 struct Map {
     width: usize,
     height: usize,
-    points: Vec<bool>
+    words_per_row: usize,
+    rows: Vec<u64>, // packed, a tree at x is bit x % 64 of word x / 64 of its row
+    terrain: Option<Vec<Terrain>>, // only kept if there's more than open ground and trees
+    boundary_x: Boundary,
+    boundary_y: Boundary
 }
 
 impl Map {
 
+    /// Parses the map, panicking if it is malformed. See parse() for the details.
+    #[cfg(test)] // main reports parse errors itself instead of panicking
     pub fn new_from_str(map: &str) -> Self {
+        Self::parse(map).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Parses a map made of the terrain glyphs. Empty lines are skipped, all other rows must have
+    ///  the same length.
+    pub fn parse(map: &str) -> Result<Self, ParseError> {
         let mut width : Option<usize> = None;
         let mut height = 0;
-        let mut points : Vec<bool> = Vec::new();
-        for line in map.split('\n').filter(|l| l.len() > 0) {
+        let mut cells = Vec::new();
+        for (line_index, line) in map.split('\n').enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let error = |column, kind| ParseError { line: line_index + 1, column, kind };
+
+            let mut length = 0;
+            for (column, c) in line.chars().enumerate() {
+                if width.is_some_and(|w| column >= w) {
+                    return Err(error(column + 1, ParseErrorKind::Ragged { expected: width.unwrap(), found: line.chars().count() }));
+                }
+                cells.push(Terrain::from_glyph(c).ok_or_else(|| error(column + 1, ParseErrorKind::UnknownGlyph(c)))?);
+                length += 1;
+            }
             match width {
-                Some(w) => assert_eq!(w, line.len()),
-                None => width = Some(line.len())
+                Some(w) if length < w => return Err(error(length + 1, ParseErrorKind::Ragged { expected: w, found: length })),
+                Some(_) => {},
+                None => width = Some(length)
             }
-            points.extend(line.chars().map(|c| c == '#'));
             height += 1;
         }
 
+        let width = width.ok_or(ParseError { line: 1, column: 1, kind: ParseErrorKind::Empty })?;
+        let mut map = Self::from_fn(width, height, |x, y| cells[x + y*width] == Terrain::Tree);
+        if cells.iter().any(|t| (*t != Terrain::Open) && (*t != Terrain::Tree)) {
+            map.terrain = Some(cells);
+        }
+        Ok(map)
+    }
+
+    /// Builds a map by asking is_tree about every cell.
+    pub fn from_fn<F: FnMut(usize, usize) -> bool>(width: usize, height: usize, mut is_tree: F) -> Self {
+        let words_per_row = width.div_ceil(64);
+        let mut rows = vec![0u64; words_per_row*height];
+        for y in 0..height {
+            for x in 0..width {
+                if is_tree(x, y) {
+                    rows[y*words_per_row + x/64] |= 1 << (x % 64);
+                }
+            }
+        }
+
         Self {
-            width: width.unwrap(),
+            width,
             height,
-            points
+            words_per_row,
+            rows,
+            terrain: None,
+            boundary_x: Boundary::Wrap,
+            boundary_y: Boundary::Error
+        }
+    }
+
+    /// Changes what lies beyond the edges. By default the map wraps horizontally and looking
+    ///  above or below it is an error.
+    pub fn with_boundaries(mut self, boundary_x: Boundary, boundary_y: Boundary) -> Self {
+        self.boundary_x = boundary_x;
+        self.boundary_y = boundary_y;
+        self
+    }
+
+    /// Index of the cell (x + y*width), after applying the boundaries. None if the cell is outside
+    ///  the map, but the boundary says it's empty.
+    pub fn locate(&self, x: isize, y: isize) -> Result<Option<usize>, OutOfBounds> {
+        let w : isize = self.width.try_into().unwrap();
+        let h : isize = self.height.try_into().unwrap();
+        let error = OutOfBounds { x, y, width: self.width, height: self.height };
+
+        let x = self.boundary_x.resolve(x, w).map_err(|_| error)?;
+        let y = self.boundary_y.resolve(y, h).map_err(|_| error)?;
+        Ok(x.zip(y).map(|(x, y)| (x + y*w) as usize))
+    }
+
+    /// Whether there is a tree at the cell. Unlike indexing, this doesn't panic.
+    pub fn get(&self, x: isize, y: isize) -> Result<bool, OutOfBounds> {
+        self.locate(x, y).map(|index| index.is_some_and(|i| self.tree_at(i % self.width, i / self.width)))
+    }
+
+    /// The terrain at the cell. Unlike indexing, this doesn't panic.
+    pub fn terrain(&self, x: isize, y: isize) -> Result<Terrain, OutOfBounds> {
+        self.locate(x, y).map(|index| match index {
+            Some(i) => self.terrain_at(i % self.width, i / self.width),
+            None => Terrain::Open
+        })
+    }
+
+    /// The terrain at a cell inside the map.
+    pub fn terrain_at(&self, x: usize, y: usize) -> Terrain {
+        match &self.terrain {
+            Some(terrain) => terrain[x + y*self.width],
+            None if self.tree_at(x, y) => Terrain::Tree,
+            None => Terrain::Open
         }
     }
+
+    /// Whether there is a tree at a cell inside the map.
+    pub fn tree_at(&self, x: usize, y: usize) -> bool {
+        (self.row(y)[x / 64] >> (x % 64)) & 1 == 1
+    }
+
+    /// The packed cells of one row, without any wrapping.
+    pub fn row(&self, y: usize) -> &[u64] {
+        &self.rows[y*self.words_per_row..(y + 1)*self.words_per_row]
+    }
 }
 
 fn modulus(a: isize, b: isize) -> isize {
@@ -40,52 +289,518 @@ impl Index<(isize,isize)> for Map {
     type Output = bool;
 
     fn index(&self, index: (isize, isize)) -> &bool {
-        let w : isize = self.width.try_into().unwrap();
-        let h : isize = self.height.try_into().unwrap();
+        match self.locate(index.0, index.1) {
+            Ok(Some(offset)) if self.tree_at(offset % self.width, offset / self.width) => &true,
+            Ok(_) => &false,
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+/// A cell visited while walking across the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Visit {
+    x: isize, // not wrapped, so this keeps growing to the right
+    y: isize,
+    terrain: Terrain,
+    is_tree: bool
+}
+
+/// Walks from the top left corner with a fixed slope until leaving the map at the top or bottom,
+///  or at a side with an error boundary. A vertical slope of 0 never leaves the map, so the walk
+///  has to be stopped by the caller.
+struct Trajectory<'a> {
+    map: &'a Map,
+    x: isize,
+    y: isize,
+    slope_x: isize,
+    slope_y: isize
+}
+
+impl<'a> Iterator for Trajectory<'a> {
+    type Item = Visit;
 
-        if (index.1 < 0) || (index.1 >= h) {
-            panic!("Map Y index out of bounds. Requested y={}, but height={}", index.1, h);
+    fn next(&mut self) -> Option<Visit> {
+        let height : isize = self.map.height.try_into().unwrap();
+        if (self.y < 0) || (self.y >= height) {
+            return None;
         }
-        let offset = modulus(index.0,w) + index.1*w;
-        let offset_u : usize = offset.try_into().unwrap();
-        &self.points[offset_u]
+        let terrain = self.map.terrain(self.x, self.y).ok()?;
+        let visit = Visit {
+            x: self.x,
+            y: self.y,
+            terrain,
+            is_tree: terrain == Terrain::Tree
+        };
+        self.x += self.slope_x;
+        self.y += self.slope_y;
+        Some(visit)
     }
 }
 
+impl Map {
+    fn trajectory(&self, slope_x: isize, slope_y: isize) -> Trajectory<'_> {
+        Trajectory {
+            map: self,
+            x: 0,
+            y: 0,
+            slope_x,
+            slope_y
+        }
+    }
+}
+
+/// Total cost of the cells visited with the slope.
+fn path_cost(map: &Map, slope_x: isize, slope_y: isize, costs: &CostModel) -> u64 {
+    map.trajectory(slope_x, slope_y).fold(0, |cost, visit| cost + costs.cost(visit.terrain))
+}
+
 fn count_trees(map: &Map, slope_x: isize, slope_y: isize) -> usize {
+    path_cost(map, slope_x, slope_y, &CostModel::trees()) as usize
+}
+
+/// How a line is turned into cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Raster {
+    Bresenham, // one cell per step along the longer axis, moving diagonally where needed
+    Supercover // every cell the line touches, including both sides when it passes a corner
+}
+
+/// The cells on the line from the centre of the top left cell in direction slope_x/slope_y, down
+///  to the given height. Uses integer arithmetic only, so fractional slopes like 1/3 are exact.
+///  X coordinates are not wrapped.
+fn raster_line(slope_x: isize, slope_y: isize, height: isize, raster: Raster) -> Vec<(isize,isize)> {
+    assert!(slope_y > 0, "Line must go downwards, but slope is {}/{}", slope_x, slope_y);
+    let step_x = slope_x.signum();
+    let nx = slope_x.abs();
+    let ny = slope_y;
+
+    let mut cells = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    match raster {
+        Raster::Bresenham => {
+            let mut error = nx - ny;
+            while y < height {
+                cells.push((x, y));
+                let e2 = 2*error;
+                if e2 > -ny {
+                    error -= ny;
+                    x += step_x;
+                }
+                if e2 < nx {
+                    error += nx;
+                    y += 1;
+                }
+            }
+        },
+        Raster::Supercover => {
+            // steps taken along each axis. comparing where the line crosses the next vertical
+            //  and horizontal cell border decides which way to go
+            let (mut ix, mut iy) = (0, 0);
+            while y < height {
+                cells.push((x, y));
+                let decision = (1 + 2*ix)*ny - (1 + 2*iy)*nx;
+                if decision == 0 {
+                    // exactly through a corner, so the line touches both neighbours
+                    cells.push((x + step_x, y));
+                    if y + 1 < height {
+                        cells.push((x, y + 1));
+                    }
+                    x += step_x;
+                    y += 1;
+                    ix += 1;
+                    iy += 1;
+                }else if decision < 0 {
+                    x += step_x;
+                    ix += 1;
+                }else{
+                    y += 1;
+                    iy += 1;
+                }
+            }
+        }
+    }
+    cells
+}
+
+fn count_trees_on_line(map: &Map, slope_x: isize, slope_y: isize, raster: Raster) -> usize {
     let height : isize = map.height.try_into().unwrap();
-    let mut x : isize = 0;
-    let mut y : isize = 0;
-    let mut trees_encountered = 0;
-    while (y >= 0) && (y < height) {
-        let is_tree = map[(x,y)];
-        println!("Walking to {}/{}. Tree: {}", x, y, is_tree);
-        if is_tree {
-            trees_encountered += 1;
+    raster_line(slope_x, slope_y, height, raster).iter()
+                                                 .map_while(|(x, y)| map.get(*x, *y).ok())
+                                                 .filter(|is_tree| *is_tree)
+                                                 .count()
+}
+
+fn gcd(a: isize, b: isize) -> isize {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+/// Parses a slope given as "dx/dy" or as a decimal number of cells right per cell down, like
+///  "0.25". The result is reduced, so "2/4" and "0.5" both become 1/2.
+fn parse_slope(s: &str) -> Result<(isize,isize), String> {
+    let (x, y) = match s.split_once('/') {
+        Some((x, y)) => {
+            let x : isize = x.trim().parse().map_err(|e| format!("Invalid dx {}: {}", x, e))?;
+            let y : isize = y.trim().parse().map_err(|e| format!("Invalid dy {}: {}", y, e))?;
+            (x, y)
+        },
+        None => {
+            let (whole, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+            if !fraction.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("Invalid slope {}", s));
+            }
+            let y = 10isize.checked_pow(fraction.len() as u32).ok_or_else(|| format!("Too many decimals in {}", s))?;
+            let x : isize = format!("{}{}", whole, fraction).parse().map_err(|e| format!("Invalid slope {}: {}", s, e))?;
+            (x, y)
         }
-        x += slope_x;
-        y += slope_y;
+    };
+    if y <= 0 {
+        return Err(format!("Slope {} does not go downwards", s));
     }
-    trees_encountered
+    let divisor = gcd(x, y);
+    Ok((x / divisor, y / divisor))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PathResult {
+    cells: Vec<(usize,usize)>, // wrapped into the map, from the top row to the bottom row
+    trees: usize
+}
+
+/// Finds the path from any cell in the top row to any cell in the bottom row that passes the
+///  fewest trees, and of those the one with the fewest steps. Each step is one of the given moves.
+///  Stepping over the left or right edge follows the map's boundary, but the path never leaves
+///  the map. Returns None if the bottom row can't be reached.
+fn find_least_trees_path(map: &Map, moves: &[(isize,isize)]) -> Option<PathResult> {
+    let height : isize = map.height.try_into().unwrap();
+    let cell_cost = |index: usize| map.tree_at(index % map.width, index / map.width) as usize;
+
+    // (trees, steps) to reach each cell, and where we came from
+    let mut best : Vec<Option<(usize,usize)>> = vec![None; map.width*map.height];
+    let mut previous : Vec<Option<usize>> = vec![None; map.width*map.height];
+    let mut queue = BinaryHeap::new();
+    for (x, start) in best.iter_mut().enumerate().take(map.width) {
+        *start = Some((cell_cost(x), 0));
+        queue.push(Reverse((cell_cost(x), 0, x)));
+    }
+
+    while let Some(Reverse((trees, steps, index))) = queue.pop() {
+        if best[index] != Some((trees, steps)) {
+            continue; // already reached more cheaply
+        }
+        let x = (index % map.width) as isize;
+        let y = (index / map.width) as isize;
+        if y == height - 1 {
+            // the first bottom cell off the queue is the cheapest one
+            let mut cells = vec![(x as usize, y as usize)];
+            let mut at = index;
+            while let Some(from) = previous[at] {
+                cells.push((from % map.width, from / map.width));
+                at = from;
+            }
+            cells.reverse();
+            return Some(PathResult { cells, trees });
+        }
+
+        for (dx, dy) in moves {
+            if (y + dy < 0) || (y + dy >= height) {
+                continue;
+            }
+            let next = match map.locate(x + dx, y + dy) {
+                Ok(Some(next)) => next,
+                _ => continue // the path has to stay on the map
+            };
+            let cost = (trees + cell_cost(next), steps + 1);
+            if best[next].is_none_or(|b| cost < b) {
+                best[next] = Some(cost);
+                previous[next] = Some(index);
+                queue.push(Reverse((cost.0, cost.1, next)));
+            }
+        }
+    }
+    None
+}
+
+/// Parses moves like "-1,1 0,1 1,1".
+fn parse_moves(s: &str) -> Result<Vec<(isize,isize)>, String> {
+    s.split_whitespace().map(|m| {
+        let (dx, dy) = m.split_once(',').ok_or_else(|| format!("Move {} is not \"dx,dy\"", m))?;
+        let dx : isize = dx.parse().map_err(|e| format!("Invalid dx in move {}: {}", m, e))?;
+        let dy : isize = dy.parse().map_err(|e| format!("Invalid dy in move {}: {}", m, e))?;
+        if (dx, dy) == (0, 0) {
+            return Err("Move 0,0 goes nowhere".to_string());
+        }
+        Ok((dx, dy))
+    }).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SlopeResult {
+    slope_x: isize,
+    slope_y: isize,
+    trees: usize,
+    path_length: f64 // straight line distance from the first to the last visited cell
+}
+
+/// Counts the trees for many slopes at once. Slopes with the same vertical step go in batches of
+///  64: for every visited row, the hits of the whole batch are collected in one word, and every 64
+///  rows these words are transposed so that a popcount gives the trees per slope.
+///
+///  This needs horizontal wrapping and slopes that go down. Anything else is left to count_trees(),
+///  except for a vertical step of 0, which never leaves the map and is an error.
+fn count_trees_many(map: &Map, slopes: &[(isize,isize)]) -> Result<Vec<usize>, String> {
+    if let Some((slope_x, slope_y)) = slopes.iter().find(|(_, y)| *y == 0) {
+        return Err(format!("Slope {}/{} never reaches the bottom of the map", slope_x, slope_y));
+    }
+    let width : isize = map.width.try_into().unwrap();
+    let mut counts = vec![0; slopes.len()];
+    let mut by_slope_y : BTreeMap<isize, Vec<usize>> = BTreeMap::new();
+    for (index, (slope_x, slope_y)) in slopes.iter().enumerate() {
+        if (map.boundary_x == Boundary::Wrap) && (*slope_y >= 1) {
+            by_slope_y.entry(*slope_y).or_default().push(index);
+        }else{
+            counts[index] = count_trees(map, *slope_x, *slope_y);
+        }
+    }
+
+    for (slope_y, indices) in by_slope_y {
+        for batch in indices.chunks(64) {
+            let steps : Vec<usize> = batch.iter().map(|i| modulus(slopes[*i].0, width) as usize).collect();
+            let mut xs = vec![0; batch.len()];
+            let mut block = [0u64; 64];
+            let mut filled = 0;
+            let mut flush = |block: &mut [u64; 64]| {
+                transpose(block);
+                for (slope, index) in batch.iter().enumerate() {
+                    counts[*index] += block[slope].count_ones() as usize;
+                }
+                *block = [0; 64];
+            };
+
+            for y in (0..map.height).step_by(slope_y as usize) {
+                let row = map.row(y);
+                let mut hits = 0u64;
+                for (slope, x) in xs.iter_mut().enumerate() {
+                    hits |= ((row[*x / 64] >> (*x % 64)) & 1) << slope;
+                    *x += steps[slope];
+                    if *x >= map.width {
+                        *x -= map.width;
+                    }
+                }
+                block[filled] = hits;
+                filled += 1;
+                if filled == 64 {
+                    flush(&mut block);
+                    filled = 0;
+                }
+            }
+            if filled > 0 {
+                flush(&mut block);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Transposes a 64x64 bit matrix in place, so that bit c of word r ends up as bit r of word c.
+fn transpose(m: &mut [u64; 64]) {
+    // swap ever smaller blocks across the diagonal: 32x32, then 16x16 and so on
+    let mut size = 32;
+    let mut mask : u64 = 0x0000_0000_ffff_ffff;
+    while size != 0 {
+        let mut r = 0;
+        while r < 64 {
+            let t = ((m[r] >> size) ^ m[r + size]) & mask;
+            m[r] ^= t << size;
+            m[r + size] ^= t;
+            r = (r + size + 1) & !size;
+        }
+        size >>= 1;
+        mask ^= mask << size;
+    }
+}
+
+/// Tries every slope in the given ranges and returns the best ones, ranked by fewest trees and
+///  then by shortest path. Vertical slopes below 1 never reach the bottom and are skipped.
+///
+///  On wrapping maps, horizontal steps that are equal modulo the map width hit the same cells, so
+///  each of them is counted once, and all of them together with count_trees_many().
+fn find_best_slopes(map: &Map, x_range: RangeInclusive<isize>, y_range: RangeInclusive<isize>, top: usize) -> Vec<SlopeResult> {
+    let width : isize = map.width.try_into().unwrap();
+    let slopes : Vec<(isize,isize)> = y_range.filter(|y| *y >= 1)
+                                             .flat_map(|y| x_range.clone().map(move |x| (x, y)))
+                                             .collect();
+
+    let tree_counts : Vec<Option<usize>> = if map.boundary_x == Boundary::Wrap {
+        let mut distinct : Vec<(isize,isize)> = slopes.iter().map(|(x, y)| (modulus(*x, width), *y)).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let counts = count_trees_many(map, &distinct).expect("Slopes were filtered to go down");
+        slopes.iter().map(|(x, y)| Some(counts[distinct.binary_search(&(modulus(*x, width), *y)).unwrap()])).collect()
+    }else{
+        // walks that run into an error at the side don't reach the bottom, so they don't count
+        slopes.iter().map(|(x, y)| {
+            let visits : Vec<Visit> = map.trajectory(*x, *y).collect();
+            if visits.len() < map.height.div_ceil(*y as usize) {
+                None
+            }else{
+                Some(visits.iter().filter(|v| v.is_tree).count())
+            }
+        }).collect()
+    };
+
+    let mut results : Vec<SlopeResult> = slopes.iter().zip(tree_counts).filter_map(|((slope_x, slope_y), trees)| {
+        let steps = (map.height.div_ceil(*slope_y as usize) - 1) as f64;
+        Some(SlopeResult {
+            slope_x: *slope_x,
+            slope_y: *slope_y,
+            trees: trees?,
+            path_length: steps * (*slope_x as f64).hypot(*slope_y as f64)
+        })
+    }).collect();
+
+    results.sort_by(|a, b| a.trees.cmp(&b.trees)
+                             .then(a.path_length.partial_cmp(&b.path_length).unwrap())
+                             .then(a.slope_y.cmp(&b.slope_y))
+                             .then(a.slope_x.cmp(&b.slope_x)));
+    results.truncate(top);
+    results
+}
+
+fn main() {
+    // usage: day3 [options] [search <max dx> <max dy> [top N]]
+    //        day3 [options] line <dx/dy or decimal> [bresenham|supercover]
+    //        day3 [options] path [moves, default "-1,1 0,1 1,1"]
+    //        day3 bench <width> <height>
+    //        day3 [options] render <ppm|png|ansi> <output file, - for stdout> [slopes, default "1,1 3,1 5,1 7,1 1,2"] [folded]
+    // options: --x-boundary and --y-boundary, each wrap, clamp, empty or error
+    //          --map <file> instead of the puzzle input
+    //          --costs <terrain=cost,...> to also print the cost of each slope
+    let mut boundary_x = Boundary::Wrap;
+    let mut boundary_y = Boundary::Error;
+    let mut map_path = "day3/input.txt".to_string();
+    let mut costs = None;
+    let mut args : Vec<String> = Vec::new();
+    let mut arg_iter = std::env::args().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--x-boundary" || arg == "--y-boundary" {
+            let mode = arg_iter.next().and_then(|m| Boundary::parse(&m)).expect("Boundary must be wrap, clamp, empty or error");
+            if arg == "--x-boundary" { boundary_x = mode } else { boundary_y = mode }
+        }else if arg == "--map" {
+            map_path = arg_iter.next().expect("--map needs a file");
+        }else if arg == "--costs" {
+            let model = arg_iter.next().expect("--costs needs costs like tree=1,rock=5");
+            costs = Some(CostModel::parse(&model).unwrap_or_else(|e| panic!("Invalid costs: {}", e)));
+        }else{
+            args.push(arg);
+        }
+    }
+
+    let map_string = std::fs::read_to_string(&map_path).expect("Failed to open map");
+    let map = match Map::parse(&map_string) {
+        Ok(map) => map.with_boundaries(boundary_x, boundary_y),
+        Err(e) => {
+            println!("Invalid map {}: {}", map_path, e);
+            std::process::exit(1);
+        }
+    };
+
+    if args.first().map(|a| a.as_str()) == Some("render") {
+        let format = args.get(1).expect("render needs a format (ppm, png or ansi)");
+        let path = args.get(2).expect("render needs an output file");
+        let slopes = parse_moves(args.get(3).map(|s| s.as_str()).unwrap_or("1,1 3,1 5,1 7,1 1,2")).unwrap_or_else(|e| panic!("{}", e));
+        let folded = args.get(4).map(|f| f.as_str()) == Some("folded");
+        let canvas = render::draw(&map, &slopes, folded).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut out : Box<dyn std::io::Write> = if path == "-" {
+            Box::new(std::io::stdout())
+        }else{
+            Box::new(std::io::BufWriter::new(std::fs::File::create(path).expect("Failed to create output file")))
+        };
+        match format.as_str() {
+            "ppm" => canvas.write_ppm(&mut out, 4),
+            "png" => canvas.write_png(&mut out, 4),
+            "ansi" => canvas.write_ansi(&mut out),
+            _ => panic!("Unknown format {}, must be ppm, png or ansi", format)
+        }.expect("Failed to write rendering");
+        return;
+    }
+
+    if args.first().map(|a| a.as_str()) == Some("bench") {
+        let width : usize = args.get(1).expect("bench needs a width").parse().expect("Invalid width");
+        let height : usize = args.get(2).expect("bench needs a height").parse().expect("Invalid height");
+        bench::run(width, height);
+        return;
+    }
+
+    if args.first().map(|a| a.as_str()) == Some("search") {
+        let max_x : isize = args.get(1).expect("search needs a maximum dx").parse().expect("Invalid dx");
+        let max_y : isize = args.get(2).expect("search needs a maximum dy").parse().expect("Invalid dy");
+        let top : usize = args.get(3).map(|t| t.parse().expect("Invalid top N")).unwrap_or(10);
+        for (rank, result) in find_best_slopes(&map, -max_x..=max_x, 1..=max_y, top).iter().enumerate() {
+            println!("{:>3}. slope {}/{}: {} trees, path length {:.1}", rank + 1, result.slope_x, result.slope_y, result.trees, result.path_length);
+        }
+        return;
+    }
+
+    if args.first().map(|a| a.as_str()) == Some("line") {
+        let (slope_x, slope_y) = parse_slope(args.get(1).expect("line needs a slope")).unwrap_or_else(|e| panic!("{}", e));
+        let raster = match args.get(2).map(|r| r.as_str()) {
+            None | Some("bresenham") => Raster::Bresenham,
+            Some("supercover") => Raster::Supercover,
+            Some(r) => panic!("Unknown raster {}, must be bresenham or supercover", r)
+        };
+        let trees = count_trees_on_line(&map, slope_x, slope_y, raster);
+        println!("Encountered {} trees along the line with slope {}/{} ({:?})", trees, slope_x, slope_y, raster);
+        return;
+    }
+
+    if args.first().map(|a| a.as_str()) == Some("path") {
+        let moves = parse_moves(args.get(1).map(|m| m.as_str()).unwrap_or("-1,1 0,1 1,1")).unwrap_or_else(|e| panic!("{}", e));
+        match find_least_trees_path(&map, &moves) {
+            Some(path) => {
+                println!("Least trees from top to bottom: {} in {} steps", path.trees, path.cells.len() - 1);
+                let cells : Vec<String> = path.cells.iter().map(|(x, y)| format!("{}/{}", x, y)).collect();
+                println!("{}", cells.join(" "));
+            },
+            None => println!("The bottom row can't be reached with these moves")
+        }
+        return;
+    }
+
+    let slopes = [(1,1), (3,1), (5,1), (7,1), (1,2)];
+    let mut mult_trees = 1;
+    for slope in &slopes {
+        let trees = count_trees(&map, slope.0, slope.1);
+        println!("Encountered {} trees with slope {}/{}", trees, slope.0, slope.1);
+        if let Some(costs) = &costs {
+            println!("  path cost: {}", path_cost(&map, slope.0, slope.1, costs));
+        }
+
+        mult_trees *= trees;
+    }
+
+    println!("Multiplied together: {}", mult_trees);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const TEST_MAP : &str = concat!(  "..##.......\n",
-                                      "#...#...#..\n",
-                                      ".#....#..#.\n",
-                                      "..#.#...#.#\n",
-                                      ".#...##..#.\n",
-                                      "..#.##.....\n",
-                                      ".#.#.#....#\n",
-                                      ".#........#\n",
-                                      "#.##...#...\n",
-                                      "#...##....#\n",
-                                      ".#..#...#.#\n");
+    pub const TEST_MAP : &str = concat!(  "..##.......\n",
+                                          "#...#...#..\n",
+                                          ".#....#..#.\n",
+                                          "..#.#...#.#\n",
+                                          ".#...##..#.\n",
+                                          "..#.##.....\n",
+                                          ".#.#.#....#\n",
+                                          ".#........#\n",
+                                          "#.##...#...\n",
+                                          "#...##....#\n",
+                                          ".#..#...#.#\n");
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn map_access() {
         let map = Map::new_from_str(TEST_MAP);
 
@@ -104,6 +819,7 @@ mod tests {
 
     #[test]
     #[should_panic]
+    #[allow(clippy::no_effect)]
     fn out_of_bounds() {
         let map = Map::new_from_str(TEST_MAP);
         map[(3,11)];
@@ -136,21 +852,278 @@ mod tests {
 
         assert_eq!(trees1*trees2*trees3*trees4*trees5, 336);
     }
-}
 
+    #[test]
+    fn trajectory() {
+        let map = Map::new_from_str(TEST_MAP);
 
-fn main() {
-    let map_string = std::fs::read_to_string("day3/input.txt").unwrap();
-    let map = Map::new_from_str(&map_string);
+        let visits : Vec<Visit> = map.trajectory(3, 1).take(3).collect();
+        assert_eq!(visits, vec![
+            Visit { x: 0, y: 0, terrain: Terrain::Open, is_tree: false },
+            Visit { x: 3, y: 1, terrain: Terrain::Open, is_tree: false },
+            Visit { x: 6, y: 2, terrain: Terrain::Tree, is_tree: true }
+        ]);
+        assert_eq!(map.trajectory(3, 1).count(), 11);
+        assert_eq!(map.trajectory(1, 2).count(), 6);
+        assert_eq!(map.trajectory(3, 1).last(), Some(Visit { x: 30, y: 10, terrain: Terrain::Tree, is_tree: true }));
 
-    let slopes = [(1,1), (3,1), (5,1), (7,1), (1,2)];
-    let mut mult_trees = 1;
-    for slope in &slopes {
-        let trees = count_trees(&map, slope.0, slope.1);
-        println!("Encountered {} trees with slope {}/{}", trees, slope.0, slope.1);
+        // stopping early: rows walked before the first tree
+        assert_eq!(map.trajectory(3, 1).take_while(|v| !v.is_tree).count(), 2);
+        assert_eq!(map.trajectory(0, 1).position(|v| v.is_tree), Some(1));
 
-        mult_trees *= trees;
+        // trees per segment of four rows
+        let segments = map.trajectory(3, 1).fold(vec![0; 3], |mut segments, visit| {
+            segments[visit.y as usize / 4] += visit.is_tree as usize;
+            segments
+        });
+        assert_eq!(segments, vec![1, 3, 3]);
     }
 
-    println!("Multiplied together: {}", mult_trees);
+    #[test]
+    fn slope_search() {
+        let map = Map::new_from_str(TEST_MAP);
+        let all = find_best_slopes(&map, -15..=15, -1..=4, usize::MAX);
+        assert_eq!(all.len(), 31*4);
+        for result in all.iter() {
+            assert_eq!(result.trees, count_trees(&map, result.slope_x, result.slope_y), "{}/{}", result.slope_x, result.slope_y);
+        }
+        for pair in all.windows(2) {
+            assert!((pair[0].trees, pair[0].path_length) <= (pair[1].trees, pair[1].path_length));
+        }
+
+        let best = find_best_slopes(&map, -15..=15, -1..=4, 5);
+        let slopes : Vec<(isize, isize, usize)> = best.iter().map(|r| (r.slope_x, r.slope_y, r.trees)).collect();
+        assert_eq!(slopes, vec![(-1, 4, 0), (2, 4, 0), (1, 3, 0), (-3, 4, 0), (3, 4, 0)]);
+        assert_eq!(best[3].path_length, 10.0); // -3/4 and 3/4 tie, left goes first
+    }
+
+    #[test]
+    fn slope_parsing() {
+        assert_eq!(parse_slope("1/3"), Ok((1, 3)));
+        assert_eq!(parse_slope("2/4"), Ok((1, 2)));
+        assert_eq!(parse_slope("-6/4"), Ok((-3, 2)));
+        assert_eq!(parse_slope("0.25"), Ok((1, 4)));
+        assert_eq!(parse_slope("3"), Ok((3, 1)));
+        assert_eq!(parse_slope("-1.5"), Ok((-3, 2)));
+        assert!(parse_slope("1/0").is_err());
+        assert!(parse_slope("1/-2").is_err());
+        assert!(parse_slope("a/2").is_err());
+        assert!(parse_slope("0.x").is_err());
+    }
+
+    #[test]
+    fn line_raster() {
+        assert_eq!(raster_line(1, 2, 4, Raster::Bresenham), vec![(0,0), (0,1), (1,2), (1,3)]);
+        assert_eq!(raster_line(3, 1, 2, Raster::Bresenham), vec![(0,0), (1,0), (2,1), (3,1), (4,1)]);
+        assert_eq!(raster_line(-1, 1, 3, Raster::Bresenham), vec![(0,0), (-1,1), (-2,2)]);
+
+        // the line passes the border between (0,1) and (1,1) exactly
+        assert_eq!(raster_line(1, 2, 3, Raster::Supercover), vec![(0,0), (0,1), (1,1), (1,2)]);
+        // and the corners of the diagonal
+        assert_eq!(raster_line(1, 1, 2, Raster::Supercover), vec![(0,0), (1,0), (0,1), (1,1), (2,1)]);
+    }
+
+    #[test]
+    fn line_against_integer_slopes() {
+        let map = Map::new_from_str(TEST_MAP);
+
+        // every cell the jumps land on is on the line, so the line can only hit more trees
+        for (slope_x, slope_y) in [(1,1), (3,1), (5,1), (7,1), (1,2), (-2,3), (0,1)].iter() {
+            for raster in [Raster::Bresenham, Raster::Supercover].iter() {
+                let cells = raster_line(*slope_x, *slope_y, 11, *raster);
+                assert!(map.trajectory(*slope_x, *slope_y).all(|v| cells.contains(&(v.x, v.y))));
+                assert!(count_trees_on_line(&map, *slope_x, *slope_y, *raster) >= count_trees(&map, *slope_x, *slope_y));
+            }
+        }
+
+        // with at most one column per row, Bresenham lands on exactly the same cells
+        for slope_x in -1..=1 {
+            assert_eq!(count_trees_on_line(&map, slope_x, 1, Raster::Bresenham), count_trees(&map, slope_x, 1));
+        }
+
+        assert_eq!(count_trees_on_line(&map, 1, 2, Raster::Bresenham), 5);
+    }
+
+    fn check_path(map: &Map, moves: &[(isize,isize)], path: &PathResult) {
+        assert_eq!(path.cells.first().unwrap().1, 0);
+        assert_eq!(path.cells.last().unwrap().1, map.height - 1);
+        assert_eq!(path.trees, path.cells.iter().filter(|(x, y)| map[(*x as isize, *y as isize)]).count());
+        for step in path.cells.windows(2) {
+            let dx = step[1].0 as isize - step[0].0 as isize;
+            let dy = step[1].1 as isize - step[0].1 as isize;
+            assert!(moves.iter().any(|(mx, my)| *my == dy && modulus(mx - dx, map.width as isize) == 0), "{:?}", step);
+        }
+    }
+
+    #[test]
+    fn least_trees_path() {
+        let map = Map::new_from_str(TEST_MAP);
+
+        let moves = parse_moves("-1,1 0,1 1,1").unwrap();
+        let path = find_least_trees_path(&map, &moves).unwrap();
+        check_path(&map, &moves, &path);
+        assert_eq!(path.trees, 0);
+        assert_eq!(path.cells.len(), 11);
+
+        // straight down is as good as the best column
+        let moves = parse_moves("0,1").unwrap();
+        let path = find_least_trees_path(&map, &moves).unwrap();
+        check_path(&map, &moves, &path);
+        let best_column = (0..11).map(|x| (0..11).filter(|y| map[(x, *y)]).count()).min().unwrap();
+        assert_eq!(path.trees, best_column);
+
+        // a single slope is as good as its best starting column, wrapping included
+        let moves = parse_moves("3,1").unwrap();
+        let path = find_least_trees_path(&map, &moves).unwrap();
+        check_path(&map, &moves, &path);
+        let best_start = (0..11).map(|x| (0..11).filter(|y| map[(x + 3*y, *y)]).count()).min().unwrap();
+        assert_eq!(path.trees, best_start);
+
+        // sideways moves are allowed, but never worth an extra step when there's no tree to avoid
+        let moves = parse_moves("1,0 -1,0 0,1").unwrap();
+        let path = find_least_trees_path(&map, &moves).unwrap();
+        check_path(&map, &moves, &path);
+        assert_eq!(path.trees, 0);
+
+        assert_eq!(find_least_trees_path(&map, &parse_moves("1,0 -1,0").unwrap()), None);
+        assert!(parse_moves("0,0").is_err());
+        assert!(parse_moves("1;1").is_err());
+    }
+
+    #[test]
+    fn boundaries() {
+        let map = Map::new_from_str(TEST_MAP);
+        assert_eq!(map.get(-3, 3), Ok(true));
+        assert_eq!(map.get(3, 11), Err(OutOfBounds { x: 3, y: 11, width: 11, height: 11 }));
+
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Clamp, Boundary::Clamp);
+        assert_eq!(map.get(-5, 1), Ok(true));   // 0/1
+        assert_eq!(map.get(20, 3), Ok(true));   // 10/3
+        assert_eq!(map.get(0, 50), Ok(false));  // 0/10
+        assert_eq!(map.get(-1, -1), Ok(false)); // 0/0
+
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Empty, Boundary::Wrap);
+        assert_eq!(map.get(-1, 1), Ok(false));
+        assert_eq!(map.get(0, 12), Ok(true)); // 0/1
+        assert_eq!(map.locate(11, 0), Ok(None));
+        assert!(!map[(11, 0)]);
+
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Error, Boundary::Empty);
+        assert!(map.get(11, 0).is_err());
+        assert_eq!(map.get(0, -1), Ok(false));
+    }
+
+    #[test]
+    fn walks_with_boundaries() {
+        // without wrapping, slope 3/1 runs out of the right side after four steps
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Error, Boundary::Error);
+        assert_eq!(map.trajectory(3, 1).count(), 4);
+        assert_eq!(count_trees(&map, 3, 1), 1);
+
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Empty, Boundary::Error);
+        assert_eq!(map.trajectory(3, 1).count(), 11);
+        assert_eq!(count_trees(&map, 3, 1), 1);
+
+        // the search leaves out walks that can't reach the bottom
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Error, Boundary::Error);
+        let all = find_best_slopes(&map, -3..=3, 1..=1, usize::MAX);
+        let slopes : Vec<isize> = all.iter().map(|r| r.slope_x).collect();
+        assert_eq!(slopes.len(), 2);
+        assert!(slopes.contains(&0) && slopes.contains(&1));
+
+        // the path can't take a shortcut around the sides
+        let moves = parse_moves("-1,1 0,1 1,1").unwrap();
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Error, Boundary::Error);
+        let path = find_least_trees_path(&map, &moves).unwrap();
+        assert!(path.cells.windows(2).all(|step| (step[1].0 as isize - step[0].0 as isize).abs() <= 1));
+    }
+
+    #[test]
+    fn bit_transpose() {
+        let original : Vec<u64> = (0..64).map(|i| bench::random_bits(7, i)).collect();
+        let mut m = [0u64; 64];
+        m.copy_from_slice(&original);
+        transpose(&mut m);
+        for (r, row) in original.iter().enumerate() {
+            for (c, column) in m.iter().enumerate() {
+                assert_eq!((column >> r) & 1, (row >> c) & 1, "{}/{}", r, c);
+            }
+        }
+    }
+
+    #[test]
+    fn many_slopes() {
+        let slopes : Vec<(isize,isize)> = (-2..=5).flat_map(|y| (-20..20).map(move |x| (x, y)))
+                                                  .filter(|(_, y)| *y != 0)
+                                                  .collect();
+
+        let map = Map::new_from_str(TEST_MAP);
+        let expected : Vec<usize> = slopes.iter().map(|(x, y)| count_trees(&map, *x, *y)).collect();
+        assert_eq!(count_trees_many(&map, &slopes), Ok(expected));
+        assert_eq!(count_trees_many(&map, &[(3, 1)]), Ok(vec![7]));
+        assert!(count_trees_many(&map, &[(3, 1), (1, 0)]).is_err());
+
+        // more than 64 rows and slopes, and rows wider than a word
+        let map = bench::generate_map(150, 300, 30, 42);
+        let expected : Vec<usize> = slopes.iter().map(|(x, y)| count_trees(&map, *x, *y)).collect();
+        assert_eq!(count_trees_many(&map, &slopes), Ok(expected));
+
+        let map = bench::generate_map(150, 300, 30, 42).with_boundaries(Boundary::Empty, Boundary::Error);
+        let expected : Vec<usize> = slopes.iter().map(|(x, y)| count_trees(&map, *x, *y)).collect();
+        assert_eq!(count_trees_many(&map, &slopes), Ok(expected));
+    }
+
+    #[test]
+    fn generated_maps() {
+        let map = bench::generate_map(100, 50, 25, 1);
+        assert_eq!((map.width, map.height, map.words_per_row), (100, 50, 2));
+        let trees = (0..50).map(|y| map.row(y).iter().map(|w| w.count_ones() as usize).sum::<usize>()).sum::<usize>();
+        assert!(trees > 1000 && trees < 1500, "{} trees", trees);
+        assert_eq!(map.row(0)[1] >> 36, 0); // nothing beyond the width
+
+        let again = bench::generate_map(100, 50, 25, 1);
+        assert_eq!(map.rows, again.rows);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |map: &str| Map::parse(map).err().unwrap();
+        assert_eq!(error("..#\n.#\n"), ParseError { line: 2, column: 3, kind: ParseErrorKind::Ragged { expected: 3, found: 2 } });
+        assert_eq!(error("..#\n\n.#..\n"), ParseError { line: 3, column: 4, kind: ParseErrorKind::Ragged { expected: 3, found: 4 } });
+        assert_eq!(error("..#\n.ö#\n"), ParseError { line: 2, column: 2, kind: ParseErrorKind::UnknownGlyph('ö') });
+        assert_eq!(error("\n\n"), ParseError { line: 1, column: 1, kind: ParseErrorKind::Empty });
+        assert_eq!(error("..#\n.x#\n").to_string(), "Line 2, column 2: Unknown glyph 'x'");
+        assert_eq!(error("..#\n.#\n").to_string(), "Line 2, column 3: Row has 2 cells, but the first row has 3");
+
+        // windows line endings are fine
+        let map = Map::parse("..#\r\n#..\r\n").unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert!(map.terrain.is_none());
+    }
+
+    #[test]
+    fn terrain_costs() {
+        let map = Map::parse(".^~*#\n#.^.~\n*~.#.\n").unwrap();
+        assert_eq!(map.terrain(1, 0), Ok(Terrain::Rock));
+        assert_eq!(map.terrain(7, 1), Ok(Terrain::Rock)); // wraps
+        assert_eq!(map.terrain(3, 3).err().unwrap().y, 3);
+        assert_eq!(map.get(4, 0), Ok(true));
+        assert_eq!(map.get(1, 0), Ok(false)); // rocks aren't trees
+
+        let costs = CostModel::parse("rock=10, ice=3,snow=2").unwrap();
+        assert_eq!(costs.cost(Terrain::Tree), 1);
+        assert_eq!(costs.cost(Terrain::Open), 0);
+        // 1/1 visits open, open, open
+        assert_eq!(path_cost(&map, 1, 1, &costs), 0);
+        // 2/1 visits open, rock, open
+        assert_eq!(path_cost(&map, 2, 1, &costs), 10);
+        // 4/1 visits open, ice, tree
+        assert_eq!(path_cost(&map, 4, 1, &costs), 4);
+        assert_eq!(count_trees(&map, 4, 1), 1);
+        assert_eq!(path_cost(&map, 4, 1, &CostModel::trees()), 1);
+
+        assert!(CostModel::parse("lava=3").is_err());
+        assert!(CostModel::parse("rock").is_err());
+        assert!(CostModel::parse("rock=-1").is_err());
+    }
 }
@@ -0,0 +1,290 @@
+use std::io::{self, Write};
+
+use crate::{Map, Terrain};
+
+/// What to draw for a cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Outside, // beyond an edge of the map that has no cells
+    Open,
+    Tree,
+    Ground(Terrain), // anything else that isn't open or a tree
+    Miss(usize), // visited by the slope with this index, no tree
+    Hit(usize)   // visited by the slope with this index, tree
+}
+
+/// The map with trajectories drawn over it. Where trajectories cross, the later slope wins.
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>
+}
+
+/// Canvases with more cells than this are refused, since the image would take too much memory.
+pub const MAX_CANVAS_CELLS : usize = 1 << 22;
+
+/// Draws the trajectories of the slopes over the map. If folded, everything is drawn onto a
+///  single copy of the map. Otherwise the map is repeated according to its boundaries, as far as
+///  the trajectories go. Slopes have to go down, since anything else never reaches the bottom,
+///  and the canvas can have at most MAX_CANVAS_CELLS cells.
+pub fn draw(map: &Map, slopes: &[(isize,isize)], folded: bool) -> Result<Canvas, String> {
+    if let Some((slope_x, slope_y)) = slopes.iter().find(|(_, y)| *y < 1) {
+        return Err(format!("Slope {}/{} doesn't go down the map", slope_x, slope_y));
+    }
+    // how far the trajectories could go sideways, worked out before walking them
+    let last_row = map.height.saturating_sub(1) as i128;
+    let (reach_min, reach_max) = slopes.iter()
+        .map(|(x, y)| *x as i128 * (last_row / *y as i128))
+        .fold((0, map.width as i128 - 1), |(min, max), x| (min.min(x), max.max(x)));
+    let reach_width = if folded { map.width as i128 } else { reach_max - reach_min + 1 };
+    if reach_min < isize::MIN as i128 || reach_max > isize::MAX as i128 || reach_width * map.height as i128 > MAX_CANVAS_CELLS as i128 {
+        return Err(format!("A canvas {} cells wide and {} high is too large to draw, at most {} cells are allowed", reach_width, map.height, MAX_CANVAS_CELLS));
+    }
+    let walks : Vec<Vec<_>> = slopes.iter().map(|(x, y)| map.trajectory(*x, *y).collect()).collect();
+    let map_width = map.width as isize;
+    let (min_x, max_x) = if folded {
+        (0, map_width - 1)
+    }else{
+        walks.iter().flatten().fold((0, map_width - 1), |(min, max), v| (min.min(v.x), max.max(v.x)))
+    };
+
+    let width = (max_x - min_x + 1) as usize;
+    let mut cells = Vec::with_capacity(width*map.height);
+    for y in 0..map.height as isize {
+        for x in min_x..=max_x {
+            cells.push(match map.terrain(x, y) {
+                Ok(Terrain::Tree) => Cell::Tree,
+                Ok(Terrain::Open) => Cell::Open,
+                Ok(terrain) => Cell::Ground(terrain),
+                Err(_) => Cell::Outside
+            });
+        }
+    }
+
+    for (slope, walk) in walks.iter().enumerate() {
+        for visit in walk {
+            let x = if folded {
+                match map.locate(visit.x, visit.y) {
+                    Ok(Some(index)) => (index % map.width) as isize,
+                    _ => continue // outside the single copy
+                }
+            }else{
+                visit.x
+            };
+            let index = (x - min_x) as usize + visit.y as usize*width;
+            cells[index] = if visit.is_tree { Cell::Hit(slope) } else { Cell::Miss(slope) };
+        }
+    }
+
+    Ok(Canvas {
+        width,
+        height: map.height,
+        cells
+    })
+}
+
+// one colour per slope, repeating after that
+const SLOPE_COLOURS : [[u8; 3]; 6] = [
+    [220, 50, 47],
+    [38, 139, 210],
+    [181, 137, 0],
+    [211, 54, 130],
+    [42, 161, 152],
+    [108, 113, 196]
+];
+
+impl Cell {
+    pub fn colour(&self) -> [u8; 3] {
+        match self {
+            Cell::Outside => [40, 40, 40],
+            Cell::Open => [230, 230, 220],
+            Cell::Tree => [34, 120, 50],
+            Cell::Ground(Terrain::Rock) => [130, 130, 130],
+            Cell::Ground(Terrain::Ice) => [170, 220, 240],
+            Cell::Ground(_) => [250, 250, 255],
+            Cell::Hit(slope) => SLOPE_COLOURS[slope % SLOPE_COLOURS.len()],
+            Cell::Miss(slope) => {
+                // halfway to white, so hits stand out
+                let [r, g, b] = SLOPE_COLOURS[slope % SLOPE_COLOURS.len()];
+                [r/2 + 128, g/2 + 128, b/2 + 128]
+            }
+        }
+    }
+
+    /// The character the puzzle uses for the cell.
+    pub fn symbol(&self) -> char {
+        match self {
+            Cell::Outside => ' ',
+            Cell::Open => '.',
+            Cell::Tree => '#',
+            Cell::Ground(terrain) => terrain.glyph(),
+            Cell::Miss(_) => 'O',
+            Cell::Hit(_) => 'X'
+        }
+    }
+}
+
+impl Canvas {
+    /// RGB pixels with each cell scaled up to a square of scale pixels.
+    pub fn pixels(&self, scale: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.width*self.height*scale*scale*3);
+        for row in self.cells.chunks(self.width) {
+            for _ in 0..scale {
+                for cell in row {
+                    for _ in 0..scale {
+                        pixels.extend_from_slice(&cell.colour());
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    pub fn write_ppm<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width*scale, self.height*scale)?;
+        out.write_all(&self.pixels(scale))
+    }
+
+    pub fn write_png<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, (self.width*scale) as u32, (self.height*scale) as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&self.pixels(scale)).map_err(png_error)
+    }
+
+    /// The puzzle's notation, coloured with 24 bit ANSI escapes.
+    pub fn write_ansi<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for row in self.cells.chunks(self.width) {
+            let mut current = None;
+            for cell in row {
+                // only switch colours when needed, which keeps the output a lot smaller
+                let colour = cell.colour();
+                if current != Some(colour) {
+                    write!(out, "\x1b[38;2;{};{};{}m", colour[0], colour[1], colour[2])?;
+                    current = Some(colour);
+                }
+                write!(out, "{}", cell.symbol())?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+}
+
+fn png_error(e: png::EncodingError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_MAP;
+
+    fn strip_escapes(s: &str) -> String {
+        let mut result = String::new();
+        let mut in_escape = false;
+        for c in s.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                c if !in_escape => result.push(c),
+                _ => {}
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn unwrapped() {
+        let map = Map::new_from_str(TEST_MAP);
+        let canvas = draw(&map, &[(3, 1)], false).unwrap();
+        assert_eq!((canvas.width, canvas.height), (31, 11));
+
+        let mut out = Vec::new();
+        canvas.write_ansi(&mut out).unwrap();
+        let lines : Vec<String> = String::from_utf8(out).unwrap().lines().map(strip_escapes).collect();
+        // as drawn in the puzzle
+        assert_eq!(lines[0], "O.##.........##.........##.....");
+        assert_eq!(lines[1], "#..O#...#..#...#...#..#...#...#");
+        assert_eq!(lines[2], ".#....X..#..#....#..#..#....#..");
+        assert_eq!(lines[10], ".#..#...#.#.#..#...#.#.#..#...X");
+
+        // these would never reach the bottom
+        assert!(draw(&map, &[(3, 1), (1, 0)], false).is_err());
+        assert!(draw(&map, &[(1, -1)], true).is_err());
+
+        // slopes this wide would need a huge canvas
+        assert!(draw(&map, &[(3, 1), (1_000_000, 1)], false).is_err());
+        assert!(draw(&map, &[(isize::MIN, 1)], false).is_err());
+        assert!(draw(&map, &[(isize::MAX, 1)], true).is_err());
+        assert_eq!(draw(&map, &[(1_000_000, 1)], true).unwrap().width, 11);
+    }
+
+    #[test]
+    fn folded_and_several_slopes() {
+        let map = Map::new_from_str(TEST_MAP);
+        let canvas = draw(&map, &[(3, 1), (-1, 2)], true).unwrap();
+        assert_eq!((canvas.width, canvas.height), (11, 11));
+        assert_eq!(canvas.cells[0], Cell::Miss(1)); // both start here, the later one wins
+        assert_eq!(canvas.cells[3 + 11], Cell::Miss(0));
+        assert_eq!(canvas.cells[6 + 2*11], Cell::Hit(0));
+        assert_eq!(canvas.cells[10 + 2*11], Cell::Miss(1)); // -1/2 wraps around to the right
+        assert_eq!(canvas.cells[9 + 4*11], Cell::Hit(1));
+        assert_eq!(canvas.cells[9 + 2*11], Cell::Tree);
+
+        // negative slopes extend the unwrapped canvas to the left
+        let canvas = draw(&map, &[(-1, 2)], false).unwrap();
+        assert_eq!(canvas.width, 16);
+        assert_eq!(canvas.cells[5], Cell::Miss(0));
+    }
+
+    #[test]
+    fn boundaries() {
+        use crate::Boundary;
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Empty, Boundary::Error);
+        let canvas = draw(&map, &[(2, 1)], false).unwrap();
+        assert_eq!(canvas.cells[12], Cell::Open); // nothing to repeat
+        let canvas = draw(&map, &[(2, 1)], true).unwrap();
+        assert_eq!(canvas.width, 11);
+        assert_eq!(canvas.cells[5*11 + 10], Cell::Miss(0));
+
+        let map = Map::new_from_str(TEST_MAP).with_boundaries(Boundary::Error, Boundary::Error);
+        let canvas = draw(&map, &[(0, 1)], false).unwrap();
+        assert_eq!(canvas.width, 11);
+    }
+
+    #[test]
+    fn terrain() {
+        let map = Map::parse(".^~*#\n#.^.~\n").unwrap();
+        let canvas = draw(&map, &[(1, 1)], true).unwrap();
+        assert_eq!(canvas.cells, vec![
+            Cell::Miss(0), Cell::Ground(Terrain::Rock), Cell::Ground(Terrain::Ice), Cell::Ground(Terrain::Snow), Cell::Tree,
+            Cell::Tree, Cell::Miss(0), Cell::Ground(Terrain::Rock), Cell::Open, Cell::Ground(Terrain::Ice)
+        ]);
+        let symbols : String = canvas.cells.iter().map(|c| c.symbol()).collect();
+        assert_eq!(symbols, "O^~*##O^.~");
+    }
+
+    #[test]
+    fn images() {
+        let map = Map::new_from_str(TEST_MAP);
+        let canvas = draw(&map, &[(3, 1)], true).unwrap();
+
+        let mut ppm = Vec::new();
+        canvas.write_ppm(&mut ppm, 2).unwrap();
+        assert!(ppm.starts_with(b"P6\n22 22\n255\n"));
+        assert_eq!(ppm.len(), 13 + 22*22*3);
+        assert_eq!(&ppm[13..16], &Cell::Miss(0).colour());
+
+        let mut png = Vec::new();
+        canvas.write_png(&mut png, 3).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (33, 33));
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, canvas.pixels(3));
+    }
+}
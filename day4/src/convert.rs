@@ -0,0 +1,419 @@
+use std::io::{self, Write};
+
+use crate::Passport;
+use crate::policy::{ParsePolicy, PUZZLE_FIELDS};
+
+/// Formats a batch of passports can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text, // the puzzle format, passports separated by blank lines
+    JsonLines, // one JSON object per passport and line, all values are strings
+    Csv // one column per field, empty cells for missing fields
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(Format::Text),
+            "jsonl" => Some(Format::JsonLines),
+            "csv" => Some(Format::Csv),
+            _ => None
+        }
+    }
+}
+
+pub fn write_passports<W: Write>(passports: &[Passport], format: Format, out: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(passports, out),
+        Format::JsonLines => write_json_lines(passports, out),
+        Format::Csv => write_csv(passports, out)
+    }
+}
+
+/// Reads a batch of passports, adding fields according to the policy. Errors name the line (or
+///  passport for text) that failed.
+pub fn read_passports(input: &str, format: Format, policy: &ParsePolicy) -> Result<Vec<Passport>, String> {
+    match format {
+        Format::Text => read_text(input, policy),
+        Format::JsonLines => read_json_lines(input, policy),
+        Format::Csv => read_csv(input, policy)
+    }
+}
+
+/// Writes each passport on a single line, in the order its fields were added.
+fn write_text<W: Write>(passports: &[Passport], out: &mut W) -> io::Result<()> {
+    for (index, passport) in passports.iter().enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", passport)?;
+    }
+    Ok(())
+}
+
+fn read_text(input: &str, policy: &ParsePolicy) -> Result<Vec<Passport>, String> {
+    input.split("\n\n")
+         .filter(|p| !p.trim().is_empty())
+         .enumerate()
+         .map(|(index, p)| Passport::parse(p, policy).map_err(|e| format!("Passport {}: {}", index + 1, e)))
+         .collect()
+}
+
+fn write_json_lines<W: Write>(passports: &[Passport], out: &mut W) -> io::Result<()> {
+    for passport in passports {
+        let fields : Vec<String> = passport.fields().map(|(k, v)| format!("{}: {}", json_string(k), json_string(v))).collect();
+        writeln!(out, "{{{}}}", fields.join(", "))?;
+    }
+    Ok(())
+}
+
+fn read_json_lines(input: &str, policy: &ParsePolicy) -> Result<Vec<Passport>, String> {
+    let mut passports = Vec::new();
+    for (line_index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("Line {}: {}", line_index + 1, e);
+        let mut passport = Passport::new();
+        for (key, value) in parse_json_object(line).map_err(error)? {
+            // null is the same as leaving the field out
+            if let Some(value) = value {
+                add_field(&mut passport, &key, &value, policy).map_err(error)?;
+            }
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+/// Writes one column per field. The puzzle fields come first, in their usual order, followed by
+///  any others in the order they first appear. A single table can't keep a separate field order
+///  for each passport, so that is lost.
+fn write_csv<W: Write>(passports: &[Passport], out: &mut W) -> io::Result<()> {
+    let mut columns : Vec<&str> = PUZZLE_FIELDS.to_vec();
+    for passport in passports {
+        for (key, _) in passport.fields() {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+
+    let header : Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+    writeln!(out, "{}", header.join(","))?;
+    for passport in passports {
+        let row : Vec<String> = columns.iter().map(|c| csv_field(passport.get_field(c).unwrap_or(""))).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Reads a table with a header row. Empty cells are missing fields, so fields with an empty
+///  value don't survive a trip through CSV.
+fn read_csv(input: &str, policy: &ParsePolicy) -> Result<Vec<Passport>, String> {
+    let mut lines = input.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    let columns = match lines.next() {
+        Some((line_index, header)) => parse_csv_line(header).map_err(|e| format!("Line {}: {}", line_index + 1, e))?,
+        None => return Ok(Vec::new())
+    };
+
+    let mut passports = Vec::new();
+    for (line_index, line) in lines {
+        let error = |e: String| format!("Line {}: {}", line_index + 1, e);
+        let cells = parse_csv_line(line).map_err(error)?;
+        if cells.len() != columns.len() {
+            return Err(error(format!("Expected {} cells, found {}", columns.len(), cells.len())));
+        }
+        let mut passport = Passport::new();
+        for (key, value) in columns.iter().zip(cells.iter()).filter(|(_, v)| !v.is_empty()) {
+            add_field(&mut passport, key, value, policy).map_err(error)?;
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+/// Adds a field read from JSON or CSV, making sure it can be written in the text format again.
+fn add_field(passport: &mut Passport, key: &str, value: &str, policy: &ParsePolicy) -> Result<(), String> {
+    if key.is_empty() || key.contains(|c: char| c == ':' || c.is_whitespace()) {
+        return Err(format!("Invalid key {:?}", key));
+    }
+    if value.contains(char::is_whitespace) {
+        return Err(format!("Value of {} contains whitespace", key));
+    }
+    passport.add_field(&format!("{}:{}", key, value), policy)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+}
+
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }else{
+        s.to_string()
+    }
+}
+
+fn parse_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut cells = Vec::new();
+    let mut chars = line.strip_suffix('\r').unwrap_or(line).chars().peekable();
+    loop {
+        let mut cell = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    },
+                    Some('"') => break,
+                    Some(c) => cell.push(c),
+                    None => return Err("Unterminated quoted cell".to_string())
+                }
+            }
+            if !matches!(chars.peek(), Some(',') | None) {
+                return Err("Expected a comma after a quoted cell".to_string());
+            }
+        }else{
+            while let Some(c) = chars.peek().copied().filter(|c| *c != ',') {
+                if c == '"' {
+                    return Err("Quote in an unquoted cell".to_string());
+                }
+                cell.push(c);
+                chars.next();
+            }
+        }
+        cells.push(cell);
+        if chars.next().is_none() {
+            return Ok(cells);
+        }
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Parses a flat JSON object whose values are strings, numbers or null. Numbers are kept as
+///  they were written. Keys keep their order.
+fn parse_json_object(s: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut chars = s.chars().peekable();
+    let mut fields = Vec::new();
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    };
+
+    skip_whitespace(&mut chars);
+    if chars.next() != Some('{') {
+        return Err("Expected an object".to_string());
+    }
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    }else{
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_json_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("Expected a colon after {:?}", key));
+            }
+            skip_whitespace(&mut chars);
+            let value = match chars.peek() {
+                Some('"') => Some(parse_json_string(&mut chars)?),
+                Some(c) if *c == '-' || c.is_ascii_digit() => {
+                    let mut number = String::new();
+                    while let Some(c) = chars.peek().copied().filter(|c| matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')) {
+                        number.push(c);
+                        chars.next();
+                    }
+                    Some(number)
+                },
+                Some('n') => {
+                    if !"null".chars().all(|c| chars.next() == Some(c)) {
+                        return Err(format!("Invalid value for {}", key));
+                    }
+                    None
+                },
+                _ => return Err(format!("Value of {} must be a string, number or null", key))
+            };
+            fields.push((key, value));
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => (),
+                Some('}') => break,
+                _ => return Err("Expected a comma or the end of the object".to_string())
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    if chars.next().is_some() {
+        return Err("Unexpected text after the object".to_string());
+    }
+    Ok(fields)
+}
+
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("Expected a string".to_string());
+    }
+    let mut result = String::new();
+    loop {
+        match chars.next().ok_or("Unterminated string")? {
+            '"' => return Ok(result),
+            '\\' => {
+                let c = match chars.next().ok_or("Unterminated string")? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let mut code = parse_hex4(chars)?;
+                        // characters outside the basic plane come as a surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            if chars.next() != Some('\\') || chars.next() != Some('u') {
+                                return Err("Unpaired surrogate".to_string());
+                            }
+                            let low = parse_hex4(chars)?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err("Unpaired surrogate".to_string());
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        std::char::from_u32(code).ok_or("Invalid unicode escape")?
+                    },
+                    c => return Err(format!("Invalid escape \\{}", c))
+                };
+                result.push(c);
+            },
+            c => result.push(c)
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars.next().and_then(|c| c.to_digit(16)).ok_or("Invalid unicode escape")?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BATCH : &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\nbyr:1937 iyr:2017 cid:147 hgt:183cm\n\n\
+                          iyr:2013 ecl:amb eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929 ppt:x,\"y\"\n";
+
+    fn write(passports: &[Passport], format: Format) -> String {
+        let mut out = Vec::new();
+        write_passports(passports, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn fields(p: &Passport) -> Vec<(String, String)> {
+        p.fields().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn json_lines() {
+        let passports = read_passports(BATCH, Format::Text, &ParsePolicy::default()).unwrap();
+        let json = write(&passports, Format::JsonLines);
+        assert_eq!(json.lines().next(), Some("{\"ecl\": \"gry\", \"pid\": \"860033327\", \"eyr\": \"2020\", \"hcl\": \"#fffffd\", \"byr\": \"1937\", \"iyr\": \"2017\", \"cid\": \"147\", \"hgt\": \"183cm\"}"));
+
+        let read = read_passports(&json, Format::JsonLines, &ParsePolicy::default()).unwrap();
+        assert_eq!(read.iter().map(fields).collect::<Vec<_>>(), passports.iter().map(fields).collect::<Vec<_>>());
+        assert_eq!(write(&read, Format::Text), "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd byr:1937 iyr:2017 cid:147 hgt:183cm\n\n\
+                                                iyr:2013 ecl:amb eyr:2023 pid:028048884 hcl:#cfa07d byr:1929 ppt:x,\"y\"\n");
+
+        let read = read_passports("{\"byr\": 1937, \"cid\": null, \"hcl\": \"\\u0023abc\\/\"}\n\n{ }", Format::JsonLines, &ParsePolicy::default()).unwrap();
+        assert_eq!(fields(&read[0]), vec![("byr".to_string(), "1937".to_string()), ("hcl".to_string(), "#abc/".to_string())]);
+        assert_eq!(read[1].fields().count(), 0);
+
+        assert!(read_passports("{\"byr\": true}", Format::JsonLines, &ParsePolicy::default()).is_err());
+        assert!(read_passports("{\"byr\": \"1937\"", Format::JsonLines, &ParsePolicy::default()).is_err());
+        assert!(read_passports("{\"b r\": \"1937\"}", Format::JsonLines, &ParsePolicy::default()).is_err());
+        assert!(read_passports("{\"byr\": \"19 37\"}", Format::JsonLines, &ParsePolicy::default()).is_err());
+        assert_eq!(read_passports("{}\n{\"byr\": 1} x", Format::JsonLines, &ParsePolicy::default()).err().unwrap(), "Line 2: Unexpected text after the object");
+    }
+
+    #[test]
+    fn csv() {
+        let passports = read_passports(BATCH, Format::Text, &ParsePolicy::default()).unwrap();
+        let csv = write(&passports, Format::Csv);
+        assert_eq!(csv, "byr,iyr,eyr,hgt,hcl,ecl,pid,cid,ppt\n\
+                         1937,2017,2020,183cm,#fffffd,gry,860033327,147,\n\
+                         1929,2013,2023,,#cfa07d,amb,028048884,,\"x,\"\"y\"\"\"\n");
+
+        let read = read_passports(&csv, Format::Csv, &ParsePolicy::default()).unwrap();
+        assert_eq!(read[1].get_field("ppt"), Some("x,\"y\""));
+        assert_eq!(read[1].get_field("cid"), None);
+        assert_eq!(read[1].get_field("hgt"), None);
+        for (a, b) in read.iter().zip(passports.iter()) {
+            for key in ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid", "ppt"].iter() {
+                assert_eq!(a.get_field(key), b.get_field(key));
+            }
+        }
+
+        assert!(read_passports("byr,iyr\n1937\n", Format::Csv, &ParsePolicy::default()).err().unwrap().starts_with("Line 2"));
+        assert!(read_passports("byr\n\"1937\n", Format::Csv, &ParsePolicy::default()).is_err());
+        assert!(read_passports("byr\n19\"37\n", Format::Csv, &ParsePolicy::default()).is_err());
+        assert_eq!(read_passports("\n\nbyr,\"iyr\n", Format::Csv, &ParsePolicy::default()).err().unwrap(), "Line 3: Unterminated quoted cell");
+        assert_eq!(read_passports("", Format::Csv, &ParsePolicy::default()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn policies() {
+        use crate::policy::{DuplicatePolicy, UnknownFieldPolicy};
+
+        let strict = ParsePolicy { duplicates: DuplicatePolicy::Error, unknown_fields: UnknownFieldPolicy::Reject, ..ParsePolicy::default() };
+        assert!(read_passports(BATCH, Format::Text, &strict).is_err());
+        assert!(read_passports("byr:1937 byr:1950", Format::Text, &strict).is_err());
+        assert!(read_passports("{\"byr\": \"1937\", \"byr\": \"1950\"}", Format::JsonLines, &strict).is_err());
+        assert!(read_passports("byr,ppt\n1937,x\n", Format::Csv, &strict).is_err());
+        assert_eq!(read_passports("byr,iyr\n1937,2017\n", Format::Csv, &strict).unwrap().len(), 1);
+
+        let first_wins = ParsePolicy { duplicates: DuplicatePolicy::FirstWins, ..ParsePolicy::default() };
+        let read = read_passports("{\"byr\": \"1937\", \"byr\": \"1950\"}", Format::JsonLines, &first_wins).unwrap();
+        assert_eq!(read[0].get_field("byr"), Some("1937"));
+        assert_eq!(read[0].warnings().len(), 1);
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let passports = read_passports(&input, Format::Text, &ParsePolicy::default()).unwrap();
+        assert_eq!(passports.len(), 259);
+
+        for format in [Format::Text, Format::JsonLines].iter() {
+            let read = read_passports(&write(&passports, *format), *format, &ParsePolicy::default()).unwrap();
+            assert_eq!(read.iter().map(fields).collect::<Vec<_>>(), passports.iter().map(fields).collect::<Vec<_>>());
+        }
+        let read = read_passports(&write(&passports, Format::Csv), Format::Csv, &ParsePolicy::default()).unwrap();
+        assert_eq!(read.iter().filter(|p| p.is_valid()).count(), 101);
+    }
+}
@@ -1,160 +1,291 @@
+#[macro_use]
+extern crate lazy_static;
 
-use std::collections::HashMap;
+extern crate regex;
+
+mod convert;
+mod policy;
+mod report;
+mod schema;
+mod typed;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+
+use policy::{DuplicatePolicy, FieldError, ParsePolicy, UnknownFieldPolicy};
+use schema::{Schema, ValidationError};
+use typed::TypedPassport;
+
+lazy_static! {
+    static ref DEFAULT_SCHEMA : Schema = Schema::parse(schema::DEFAULT_SCHEMA).unwrap();
+}
 
 pub struct Passport {
-    fields: HashMap<String, String>
+    fields: Vec<(String, String)>, // in the order they were first added
+    warnings: Vec<FieldError>
+}
+
+/// The key:value tokens of a passport. Any whitespace separates them, so tabs and line endings
+///  with a carriage return work as well.
+fn tokens(s: &str) -> impl Iterator<Item = &str> {
+    s.split(char::is_whitespace).filter(|t| !t.is_empty())
 }
 
 impl Passport {
 
     pub fn new() -> Self {
         Self {
-            fields: HashMap::new()
+            fields: Vec::new(),
+            warnings: Vec::new()
         }
     }
 
+    /// Parses a passport with the default policy. Malformed tokens are skipped and end up in the
+    ///  warnings, together with duplicate keys.
     pub fn new_from_str(s: &str) -> Self {
+        let policy = ParsePolicy::default();
         let mut p = Self::new();
-        for line in s.split('\n') {
-            p.add_fields(line);
+        for token in tokens(s) {
+            if let Err(e) = p.add_field(token, &policy) {
+                p.warnings.push(e);
+            }
         }
         p
     }
 
-    pub fn add_fields(&mut self, fields_str: &str) -> usize {
+    /// Parses a passport, failing on the first problem the policy doesn't allow.
+    pub fn parse(s: &str, policy: &ParsePolicy) -> Result<Self, FieldError> {
+        let mut p = Self::new();
+        p.add_fields_with(s, policy)?;
+        Ok(p)
+    }
+
+    /// Adds the whitespace separated key:value pairs with the default policy. Returns the number of
+    ///  new keys.
+    pub fn add_fields(&mut self, fields_str: &str) -> Result<usize, FieldError> {
+        self.add_fields_with(fields_str, &ParsePolicy::default())
+    }
+
+    /// Adds the whitespace separated key:value pairs and returns the number of new keys. Stops at the
+    ///  first error, so fields before it stay added.
+    pub fn add_fields_with(&mut self, fields_str: &str, policy: &ParsePolicy) -> Result<usize, FieldError> {
         let mut count = 0;
-        for field in fields_str.split(' ').filter(|f| !f.is_empty()) {
-            let mut parts = field.split(':');
-            let key = parts.next().unwrap();
-            let value = parts.next().unwrap();
-            if let Some(old_value) = self.fields.insert(key.to_string(), value.to_string()) {
-                println!("Warning! Duplicate key {}, was {}", key, old_value);
-            }else{
+        for field in tokens(fields_str) {
+            if self.add_field(field, policy)? {
                 count += 1;
             }
         }
-        count
+        Ok(count)
+    }
+
+    /// Adds a single key:value pair. Returns whether the key is new. Duplicates that are
+    ///  resolved by the policy and unknown fields with the warn policy are recorded as warnings.
+    pub fn add_field(&mut self, token: &str, policy: &ParsePolicy) -> Result<bool, FieldError> {
+        let (key, value) = match token.split_once(':') {
+            Some((key, value)) if !key.is_empty() => (key, value),
+            _ => return Err(FieldError::Malformed { token: token.to_string() })
+        };
+
+        if !policy.is_known(key) {
+            let unknown = FieldError::UnknownField { key: key.to_string(), value: value.to_string() };
+            match policy.unknown_fields {
+                UnknownFieldPolicy::Allow => (),
+                UnknownFieldPolicy::Warn => self.warnings.push(unknown),
+                UnknownFieldPolicy::Reject => return Err(unknown)
+            }
+        }
+
+        if let Some((_, old_value)) = self.fields.iter_mut().find(|(k, _)| k == key) {
+            let duplicate = FieldError::DuplicateKey { key: key.to_string(), first: old_value.clone(), second: value.to_string() };
+            match policy.duplicates {
+                DuplicatePolicy::FirstWins => (),
+                DuplicatePolicy::LastWins => *old_value = value.to_string(),
+                DuplicatePolicy::Error => return Err(duplicate)
+            }
+            self.warnings.push(duplicate);
+            Ok(false)
+        }else{
+            self.fields.push((key.to_string(), value.to_string()));
+            Ok(true)
+        }
+    }
+
+    /// Problems that were tolerated while adding fields.
+    pub fn warnings(&self) -> &[FieldError] {
+        &self.warnings
     }
 
     pub fn clear(&mut self) {
         self.fields.clear();
+        self.warnings.clear();
     }
 
     pub fn get_field<'a>(&'a self, key: &str) -> Option<&'a str> {
-        match self.fields.get(key) {
-            Some(v) => Some(v.as_str()),
-            None => None
-        }
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// All fields in the order they were added.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
     }
 
     pub fn has_required_fields(&self, keys: &[&str]) -> bool {
-        for key in keys {
-            if let None = self.get_field(key) {
-                return false;
-            }
-        }
-        true
-    }
-
-    pub fn validate_int_field(&self, key: &str, name: &str, digits: usize, min: i32, max: i32) -> bool {
-        if let Some(value) = self.get_field(key) {
-            if value.len() != digits {
-                println!("{} has invalid digit count: {}", name, value);
-                false
-            }else if let Ok(int_value) = value.parse::<i32>() {
-                (int_value >= min) && (int_value <= max)
-            }else{
-                println!("{} not parseable: {}", name, value);
-                false
-            }
-        }else{
-            println!("{} not present", name);
-            false
-        }
+        keys.iter().all(|key| self.get_field(key).is_some())
+    }
+
+    /// Checks the passport against the rules from the puzzle and returns all problems found. See
+    ///  schema.txt.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        DEFAULT_SCHEMA.validate(self)
     }
 
     pub fn is_valid(&self) -> bool {
+        DEFAULT_SCHEMA.is_valid(self)
+    }
+}
 
-        let required_fields = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
-        if !self.has_required_fields(&required_fields) {
-            return false;
-        }
+impl Default for Passport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        if !self.validate_int_field("byr", "Birth year", 4, 1920, 2002) {
-            return false;
-        }
+impl fmt::Display for Passport {
+    /// Writes the fields in the order they were added, on a single line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields : Vec<String> = self.fields().map(|(k, v)| format!("{}:{}", k, v)).collect();
+        write!(f, "{}", fields.join(" "))
+    }
+}
 
-        if !self.validate_int_field("iyr", "Issue year", 4, 2010, 2020) {
-            return false;
-        }
+/// Writes what the parse policy tolerated in the passport with the given one-based number.
+fn write_warnings<W: Write>(passport_number: usize, warnings: &[FieldError], out: &mut W) -> io::Result<()> {
+    for warning in warnings {
+        writeln!(out, "Passport {}: {}", passport_number, warning)?;
+    }
+    Ok(())
+}
 
-        if !self.validate_int_field("eyr", "Expiry year", 4, 2020, 2030) {
-            return false;
-        }
+fn main() {
 
-        let hgt = self.get_field("hgt").unwrap();
-        if let Some(cm_str) = hgt.strip_suffix("cm") {
-            if let Ok(cm) = cm_str.parse::<u32>() {
-                if cm < 150 || cm > 193 {
-                    println!("Height out of valid range: {}cm", cm);
-                    return false;
-                }
-                //println!("Valid height: {}cm", cm);
-            }else{
-                println!("Height (cm suffix) not parseable");
-                return false;
-            }
-        }else if let Some(inches_str) = hgt.strip_suffix("in") {
-            if let Ok(inches) = inches_str.parse::<u32>() {
-                if inches < 59 || inches > 76 {
-                    println!("Height out of valid range: {}in", inches);
-                    return false;
-                }
-                //println!("Valid height: {}in", inches);
-            }else{
-                println!("Height (inches suffix) not parseable");
-                return false;
-            }
+    // usage: day4 [--explain] [--normalize] [--report text|csv|json] [--duplicates first|last|error]
+    //             [--unknown allow|warn|reject] [--input path] [--convert text|jsonl|csv text|jsonl|csv]
+    //             [schema file]
+    //  --explain prints why each passport is invalid, --normalize prints the valid ones in the
+    //  canonical single line format and --report prints statistics over all passports instead.
+    //  fields not in the schema count as unknown. --convert reads the input in the first format and
+    //  prints it in the second one. the duplicate and unknown field policies apply in every mode,
+    //  and whatever they reject or warn about is printed to stderr
+    let mut input_path = "day4/input.txt".to_string();
+    let mut convert_formats = None;
+    let mut explain = false;
+    let mut duplicates = DuplicatePolicy::LastWins;
+    let mut unknown_fields = UnknownFieldPolicy::Allow;
+    let mut normalize = false;
+    let mut report_format = None;
+    let mut schema_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--report" {
+            let format = args.next().expect("--report needs a format (text, csv or json)");
+            report_format = Some(report::Format::parse(&format).expect("Report format must be text, csv or json"));
+        }else if arg == "--input" {
+            input_path = args.next().expect("--input needs a path");
+        }else if arg == "--convert" {
+            let mut format = || {
+                let format = args.next().expect("--convert needs two formats (text, jsonl or csv)");
+                convert::Format::parse(&format).expect("Conversion formats must be text, jsonl or csv")
+            };
+            convert_formats = Some((format(), format()));
+        }else if arg == "--duplicates" {
+            let policy = args.next().expect("--duplicates needs a policy (first, last or error)");
+            duplicates = DuplicatePolicy::parse(&policy).expect("Duplicate policy must be first, last or error");
+        }else if arg == "--unknown" {
+            let policy = args.next().expect("--unknown needs a policy (allow, warn or reject)");
+            unknown_fields = UnknownFieldPolicy::parse(&policy).expect("Unknown field policy must be allow, warn or reject");
+        }else if arg == "--explain" {
+            explain = true;
+        }else if arg == "--normalize" {
+            normalize = true;
         }else{
-            println!("Height has invalid suffix");
-            return false;
+            schema_path = Some(arg);
         }
+    }
+    let custom_schema = schema_path.map(|path| {
+        let s = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Could not read schema {}: {}", path, e));
+        Schema::parse(&s).unwrap_or_else(|e| panic!("Invalid schema {}: {}", path, e))
+    });
+    let schema = custom_schema.as_ref().unwrap_or(&DEFAULT_SCHEMA);
+    let policy = ParsePolicy {
+        duplicates,
+        unknown_fields,
+        known_fields: schema.fields.iter().map(|f| f.key.clone()).collect()
+    };
 
-        let hcl = self.get_field("hcl").unwrap();
-        if let Some(hcl_hex) = hcl.strip_prefix('#') {
-            if hcl_hex.len() != 6 {
-                println!("Hair color has invalid length");
-                return false;
-            }
-            let allowed_chars = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f' ];
-            for c in hcl_hex.chars() {
-                if !allowed_chars.contains(&c) {
-                    println!("Hair color has invalid character {}", c);
-                    return false;
-                }
-            }
-        }else{
-            println!("Hair color has invalid prefix: {}", hcl);
-            return false;
+    let mut have_fields = 0;
+    let mut valid_passport_count = 0;
+    let mut rejected = 0;
+
+    let passports = std::fs::read_to_string(&input_path).unwrap_or_else(|e| panic!("Could not read {}: {}", input_path, e));
+
+    if let Some((from, to)) = convert_formats {
+        let converted = convert::read_passports(&passports, from, &policy).unwrap_or_else(|e| panic!("Could not read {}: {}", input_path, e));
+        for (index, p) in converted.iter().enumerate() {
+            write_warnings(index + 1, p.warnings(), &mut io::stderr()).expect("Failed to write warnings");
         }
+        let stdout = std::io::stdout();
+        convert::write_passports(&converted, to, &mut stdout.lock()).expect("Failed to write passports");
+        return;
+    }
 
-        let valid_eye_colors = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"];
-        let eye_color = self.get_field("ecl").unwrap();
-        if !valid_eye_colors.contains(&eye_color) {
-            println!("Invalid eye color {}", eye_color);
-            return false;
+    if let Some(format) = report_format {
+        let report = report::Report::new(schema, &passports, &policy).unwrap_or_else(|e| panic!("Could not read {}: {}", input_path, e));
+        for (passport_number, warning) in report.warnings.iter() {
+            write_warnings(*passport_number, std::slice::from_ref(warning), &mut io::stderr()).expect("Failed to write warnings");
         }
+        let stdout = std::io::stdout();
+        report::write_report(&report, format, &mut stdout.lock()).expect("Failed to write report");
+        return;
+    }
 
-        if !self.validate_int_field("pid", "Passport ID", 9, 0, i32::MAX){
-            return false;
+    for (index, passport) in passports.split("\n\n").enumerate() {
+        let p = match Passport::parse(passport, &policy) {
+            Ok(p) => p,
+            Err(e) => {
+                // always reported, otherwise they'd silently drop out of the counts
+                eprintln!("Passport {} is rejected: {}", index + 1, e);
+                rejected += 1;
+                continue;
+            }
+        };
+        // whatever the duplicate and unknown field policies tolerated is always reported
+        write_warnings(index + 1, p.warnings(), &mut io::stderr()).expect("Failed to write warnings");
+        if schema.has_required_fields(&p) {
+            have_fields += 1;
+        }
+        let errors = schema.validate(&p);
+        if errors.is_empty() {
+            valid_passport_count += 1;
+            if normalize {
+                match TypedPassport::try_from(&p) {
+                    Ok(typed) => println!("{}", typed),
+                    Err(_) => println!("Passport {} passes the schema, but not the puzzle rules", index + 1)
+                }
+            }
+        }else if explain {
+            let reasons : Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            println!("Passport {} is invalid: {}", index + 1, reasons.join(", "));
         }
+    }
 
-        true
+    println!("{} passports have all required fields. Of these, {} have only valid fields", have_fields, valid_passport_count);
+    if rejected > 0 {
+        println!("{} passports were rejected by the parse policy and not counted", rejected);
     }
 }
 
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,8 +293,10 @@ mod tests {
     #[test]
     fn adding_keys() {
         let mut p = Passport::new();
-        let count = p.add_fields("ecl:gry pid:860033327 eyr:2020 hcl:#fffffd");
+        let count = p.add_fields("ecl:gry pid:860033327 eyr:2020 hcl:#fffffd").unwrap();
         assert_eq!(count, 4);
+        assert_eq!(p.fields().map(|(k, _)| k).collect::<Vec<_>>(), vec!["ecl", "pid", "eyr", "hcl"]);
+        assert_eq!(p.to_string(), "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd");
         assert_eq!(p.get_field("eyr"), Some("2020"));
         assert_eq!(p.get_field("hcl"), Some("#fffffd"));
         assert_eq!(p.get_field("oaa"), None);
@@ -171,38 +304,111 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn validity() {
         let required_fields = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
 
         let mut p = Passport::new();
-        p.add_fields("ecl:gry pid:860033327 eyr:2020 hcl:#fffffd");
-        p.add_fields("byr:1937 iyr:2017 cid:147 hgt:183cm");
+        p.add_fields("ecl:gry pid:860033327 eyr:2020 hcl:#fffffd").unwrap();
+        p.add_fields("byr:1937 iyr:2017 cid:147 hgt:183cm").unwrap();
         assert_eq!(p.has_required_fields(&required_fields), true);
 
         p.clear();
-        p.add_fields("iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884");
-        p.add_fields("hcl:#cfa07d byr:1929");
+        p.add_fields("iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884").unwrap();
+        p.add_fields("hcl:#cfa07d byr:1929").unwrap();
         assert_eq!(p.has_required_fields(&required_fields), false);
     }
-}
 
-fn main() {
+    fn policy(duplicates: DuplicatePolicy, unknown_fields: UnknownFieldPolicy) -> ParsePolicy {
+        ParsePolicy { duplicates, unknown_fields, ..ParsePolicy::default() }
+    }
 
-    let required_fields = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+    #[test]
+    fn duplicate_keys() {
+        let input = "byr:1937 iyr:2017\nbyr:1950";
+        let duplicate = FieldError::DuplicateKey { key: "byr".to_string(), first: "1937".to_string(), second: "1950".to_string() };
 
-    let mut have_fields = 0;
-    let mut valid_passport_count = 0;
+        let p = Passport::parse(input, &policy(DuplicatePolicy::FirstWins, UnknownFieldPolicy::Allow)).unwrap();
+        assert_eq!(p.get_field("byr"), Some("1937"));
+        assert_eq!(p.warnings(), std::slice::from_ref(&duplicate));
 
-    let passports = std::fs::read_to_string("day4/input.txt").unwrap();
-    for passport in passports.split("\n\n") {
-        let p = Passport::new_from_str(passport);
-        if p.has_required_fields(&required_fields) {
-            have_fields += 1;
-            if p.is_valid() {
-                valid_passport_count += 1;
-            }
-        }
+        let p = Passport::parse(input, &policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Allow)).unwrap();
+        assert_eq!(p.get_field("byr"), Some("1950"));
+        assert_eq!(p.warnings(), std::slice::from_ref(&duplicate));
+
+        assert_eq!(Passport::parse(input, &policy(DuplicatePolicy::Error, UnknownFieldPolicy::Allow)).err(), Some(duplicate));
+
+        let mut p = Passport::new();
+        assert_eq!(p.add_fields("byr:1937 byr:1937 iyr:2017"), Ok(2));
     }
 
-    println!("{} passports have all required fields. Of these, {} have only valid fields", have_fields, valid_passport_count);
+    #[test]
+    fn unknown_fields() {
+        let input = "byr:1937 ppt:yes";
+        let unknown = FieldError::UnknownField { key: "ppt".to_string(), value: "yes".to_string() };
+
+        let p = Passport::parse(input, &policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Allow)).unwrap();
+        assert_eq!(p.get_field("ppt"), Some("yes"));
+        assert!(p.warnings().is_empty());
+
+        let p = Passport::parse(input, &policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Warn)).unwrap();
+        assert_eq!(p.get_field("ppt"), Some("yes"));
+        assert_eq!(p.warnings(), std::slice::from_ref(&unknown));
+
+        assert_eq!(Passport::parse(input, &policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Reject)).err(), Some(unknown));
+
+        // warnings are written for main to report, whether or not it explains the validation
+        let p = Passport::parse(input, &policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Warn)).unwrap();
+        let mut out = Vec::new();
+        write_warnings(3, p.warnings(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Passport 3: Unknown field ppt with value yes\n");
+
+        // with a custom list of known fields
+        let custom = ParsePolicy { known_fields: vec!["ppt".to_string()], ..policy(DuplicatePolicy::LastWins, UnknownFieldPolicy::Reject) };
+        assert!(Passport::parse("ppt:yes", &custom).is_ok());
+        assert!(Passport::parse("byr:1937", &custom).is_err());
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        let mut p = Passport::new();
+        assert_eq!(p.add_fields("byr:1937 iyr2017 eyr:2020"), Err(FieldError::Malformed { token: "iyr2017".to_string() }));
+        assert_eq!(p.get_field("byr"), Some("1937"));
+        assert_eq!(p.get_field("eyr"), None);
+        assert!(p.add_fields(":2017").is_err());
+
+        // an empty value and colons in the value are fine
+        assert_eq!(p.add_fields("cid: hgt:a:b"), Ok(2));
+        assert_eq!(p.get_field("hgt"), Some("a:b"));
+
+        // the lenient parser skips malformed tokens
+        let p = Passport::new_from_str("byr:1937 iyr2017\neyr:2020");
+        assert_eq!(p.get_field("eyr"), Some("2020"));
+        assert_eq!(p.warnings(), [FieldError::Malformed { token: "iyr2017".to_string() }]);
+        assert!(Passport::parse("byr:1937 iyr2017", &ParsePolicy::default()).is_err());
+    }
+
+    #[test]
+    fn whitespace() {
+        let input = "byr:1937\tiyr:2017\r\neyr:2020  hgt:183cm\r\n";
+        let lenient = Passport::new_from_str(input);
+        let strict = Passport::parse(input, &ParsePolicy::default()).unwrap();
+        assert_eq!(lenient.to_string(), "byr:1937 iyr:2017 eyr:2020 hgt:183cm");
+        assert_eq!(strict.to_string(), lenient.to_string());
+        assert!(lenient.warnings().is_empty());
+        assert!(strict.warnings().is_empty());
+
+        let mut p = Passport::new();
+        assert_eq!(p.add_fields("ecl:gry\tpid:860033327\r"), Ok(2));
+        assert_eq!(p.get_field("pid"), Some("860033327"));
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let passports = std::fs::read_to_string("input.txt").unwrap();
+        let passports : Vec<Passport> = passports.split("\n\n").map(Passport::new_from_str).collect();
+        assert_eq!(passports.iter().filter(|p| DEFAULT_SCHEMA.has_required_fields(p)).count(), 192);
+        assert_eq!(passports.iter().filter(|p| p.is_valid()).count(), 101);
+        assert_eq!(passports.iter().filter(|p| p.validate().len() > 1).count(), 110);
+    }
 }
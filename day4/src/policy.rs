@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// The fields from the puzzle.
+pub const PUZZLE_FIELDS : [&str; 8] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
+
+/// What to do when a passport contains the same key twice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    FirstWins,
+    LastWins,
+    Error
+}
+
+/// What to do with keys that aren't in the list of known fields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownFieldPolicy {
+    Allow,
+    Warn,
+    Reject
+}
+
+impl DuplicatePolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "first" => Some(DuplicatePolicy::FirstWins),
+            "last" => Some(DuplicatePolicy::LastWins),
+            "error" => Some(DuplicatePolicy::Error),
+            _ => None
+        }
+    }
+}
+
+impl UnknownFieldPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "allow" => Some(UnknownFieldPolicy::Allow),
+            "warn" => Some(UnknownFieldPolicy::Warn),
+            "reject" => Some(UnknownFieldPolicy::Reject),
+            _ => None
+        }
+    }
+}
+
+/// How fields are added to a passport. The default keeps the last of duplicate keys and allows
+///  any field, like the puzzle parser always did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePolicy {
+    pub duplicates: DuplicatePolicy,
+    pub unknown_fields: UnknownFieldPolicy,
+    pub known_fields: Vec<String>
+}
+
+impl Default for ParsePolicy {
+    fn default() -> Self {
+        Self {
+            duplicates: DuplicatePolicy::LastWins,
+            unknown_fields: UnknownFieldPolicy::Allow,
+            known_fields: PUZZLE_FIELDS.iter().map(|f| f.to_string()).collect()
+        }
+    }
+}
+
+impl ParsePolicy {
+    pub fn is_known(&self, key: &str) -> bool {
+        self.known_fields.iter().any(|f| f == key)
+    }
+}
+
+/// A problem with the fields of a passport. Depending on the policy, these are either errors or
+///  just warnings.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldError {
+    Malformed { token: String },
+    DuplicateKey { key: String, first: String, second: String },
+    UnknownField { key: String, value: String }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Malformed { token } => write!(f, "Malformed field {:?}, expected key:value", token),
+            FieldError::DuplicateKey { key, first, second } => write!(f, "Duplicate key {}, was {}, then {}", key, first, second),
+            FieldError::UnknownField { key, value } => write!(f, "Unknown field {} with value {}", key, value)
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::Passport;
+use crate::convert::{csv_field, json_string};
+use crate::policy::{FieldError, ParsePolicy};
+use crate::schema::{Schema, ValidationError};
+
+/// Statistics over a whole batch of passports.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub passports: usize,
+    pub valid: usize,
+    pub missing: Vec<(String, usize)>, // for every field in the schema, in schema order
+    pub failures: BTreeMap<(String, &'static str), usize>, // by field and error kind, not counting missing fields
+    pub birth_decades: BTreeMap<i64, usize>, // all birth years that are numbers, valid or not
+    pub eye_colors: BTreeMap<String, usize>, // all eye color values, valid or not
+    pub multiple_failures: Vec<(usize, Vec<ValidationError>)>, // one-based passport number and its errors
+    pub warnings: Vec<(usize, FieldError)> // one-based passport number and what the policy tolerated
+}
+
+impl Report {
+    /// Validates all passports in the batch. Passports are separated by blank lines, as in the
+    ///  puzzle input. Fails on the first passport the policy rejects.
+    pub fn new(schema: &Schema, batch: &str, policy: &ParsePolicy) -> Result<Self, String> {
+        let mut report = Self {
+            passports: 0,
+            valid: 0,
+            missing: schema.fields.iter().map(|f| (f.key.clone(), 0)).collect(),
+            failures: BTreeMap::new(),
+            birth_decades: BTreeMap::new(),
+            eye_colors: BTreeMap::new(),
+            multiple_failures: Vec::new(),
+            warnings: Vec::new()
+        };
+
+        for (index, passport) in batch.split("\n\n").enumerate() {
+            if passport.trim().is_empty() {
+                continue;
+            }
+            let p = Passport::parse(passport, policy).map_err(|e| format!("Passport {}: {}", index + 1, e))?;
+            report.passports += 1;
+            report.warnings.extend(p.warnings().iter().map(|w| (index + 1, w.clone())));
+
+            for (field, count) in report.missing.iter_mut() {
+                if p.get_field(field).is_none() {
+                    *count += 1;
+                }
+            }
+            if let Some(year) = p.get_field("byr").and_then(|y| y.parse::<i64>().ok()) {
+                *report.birth_decades.entry(year.div_euclid(10) * 10).or_insert(0) += 1;
+            }
+            if let Some(color) = p.get_field("ecl") {
+                *report.eye_colors.entry(color.to_string()).or_insert(0) += 1;
+            }
+
+            let errors = schema.validate(&p);
+            for error in errors.iter().filter(|e| !matches!(e, ValidationError::MissingField { .. })) {
+                *report.failures.entry((error.field().to_string(), error.kind())).or_insert(0) += 1;
+            }
+            match errors.len() {
+                0 => report.valid += 1,
+                1 => (),
+                _ => report.multiple_failures.push((index + 1, errors))
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(Format::Text),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None
+        }
+    }
+}
+
+pub fn write_report<W: Write>(report: &Report, format: Format, out: &mut W) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(report, out),
+        Format::Csv => write_csv(report, out),
+        Format::Json => write_json(report, out)
+    }
+}
+
+fn write_text<W: Write>(report: &Report, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{} passports, {} valid, {} failing more than one rule", report.passports, report.valid, report.multiple_failures.len())?;
+
+    writeln!(out, "\nMissing fields:")?;
+    for (field, count) in report.missing.iter() {
+        writeln!(out, "  {:<8} {:>5}", field, count)?;
+    }
+
+    writeln!(out, "\nFailed checks:")?;
+    for ((field, kind), count) in report.failures.iter() {
+        writeln!(out, "  {:<8} {:<8} {:>5}", field, kind, count)?;
+    }
+
+    // bars are scaled so the largest bucket is 40 characters wide
+    let bar = |count: usize, max: usize| "#".repeat((count * 40).div_ceil(max.max(1)));
+
+    writeln!(out, "\nBirth years:")?;
+    let max = report.birth_decades.values().copied().max().unwrap_or(0);
+    for (decade, count) in report.birth_decades.iter() {
+        writeln!(out, "  {}s {:>5} {}", decade, count, bar(*count, max))?;
+    }
+
+    writeln!(out, "\nEye colors:")?;
+    let max = report.eye_colors.values().copied().max().unwrap_or(0);
+    for (color, count) in report.eye_colors.iter() {
+        writeln!(out, "  {:<8} {:>5} {}", color, count, bar(*count, max))?;
+    }
+
+    writeln!(out, "\nPassports failing more than one rule:")?;
+    for (passport, errors) in report.multiple_failures.iter() {
+        let reasons : Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        writeln!(out, "  {:>5}: {}", passport, reasons.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Writes everything as one table. The key depends on the section, count is what's counted in
+///  it, and detail holds the errors of passports failing multiple rules.
+fn write_csv<W: Write>(report: &Report, out: &mut W) -> io::Result<()> {
+    writeln!(out, "section,key,count,detail")?;
+    writeln!(out, "summary,passports,{},", report.passports)?;
+    writeln!(out, "summary,valid,{},", report.valid)?;
+    for (field, count) in report.missing.iter() {
+        writeln!(out, "missing,{},{},", csv_field(field), count)?;
+    }
+    for ((field, kind), count) in report.failures.iter() {
+        writeln!(out, "failure,{},{},", csv_field(&format!("{} {}", field, kind)), count)?;
+    }
+    for (decade, count) in report.birth_decades.iter() {
+        writeln!(out, "birth_decade,{},{},", decade, count)?;
+    }
+    for (color, count) in report.eye_colors.iter() {
+        writeln!(out, "eye_color,{},{},", csv_field(color), count)?;
+    }
+    for (passport, errors) in report.multiple_failures.iter() {
+        let reasons : Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        writeln!(out, "multiple_failures,{},{},{}", passport, errors.len(), csv_field(&reasons.join("; ")))?;
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(report: &Report, out: &mut W) -> io::Result<()> {
+    let counts = |entries: Vec<(String, usize)>| {
+        let entries : Vec<String> = entries.iter().map(|(key, count)| format!("{}: {}", json_string(key), count)).collect();
+        format!("{{{}}}", entries.join(", "))
+    };
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"passports\": {},", report.passports)?;
+    writeln!(out, "  \"valid\": {},", report.valid)?;
+    writeln!(out, "  \"missing\": {},", counts(report.missing.clone()))?;
+    let failures : Vec<String> = report.failures.iter()
+                                       .map(|((field, kind), count)| format!("{{\"field\": {}, \"kind\": \"{}\", \"count\": {}}}", json_string(field), kind, count))
+                                       .collect();
+    writeln!(out, "  \"failures\": [{}],", failures.join(", "))?;
+    writeln!(out, "  \"birth_decades\": {},", counts(report.birth_decades.iter().map(|(d, c)| (d.to_string(), *c)).collect()))?;
+    writeln!(out, "  \"eye_colors\": {},", counts(report.eye_colors.iter().map(|(e, c)| (e.clone(), *c)).collect()))?;
+    writeln!(out, "  \"multiple_failures\": [")?;
+    for (index, (passport, errors)) in report.multiple_failures.iter().enumerate() {
+        let reasons : Vec<String> = errors.iter().map(|e| json_string(&e.to_string())).collect();
+        write!(out, "    {{\"passport\": {}, \"errors\": [{}]}}", passport, reasons.join(", "))?;
+        writeln!(out, "{}", if index + 1 < report.multiple_failures.len() { "," } else { "" })?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::DEFAULT_SCHEMA;
+
+    // the examples from the puzzle, two valid and two invalid ones
+    const BATCH : &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\nbyr:1937 iyr:2017 cid:147 hgt:183cm\n\n\
+                          iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929\n\n\
+                          hcl:#ae17e1 iyr:2013\neyr:2024\necl:brn pid:760753108 byr:1931\nhgt:179cm\n\n\
+                          hcl:dab227 iyr:2012\necl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277\n\n";
+
+    fn report() -> Report {
+        Report::new(&Schema::parse(DEFAULT_SCHEMA).unwrap(), BATCH, &ParsePolicy::default()).unwrap()
+    }
+
+    #[test]
+    fn statistics() {
+        let report = report();
+        assert_eq!(report.passports, 4);
+        assert_eq!(report.valid, 2);
+        assert_eq!(report.missing.iter().filter(|(_, c)| *c > 0).collect::<Vec<_>>(), vec![&("hgt".to_string(), 1), &("cid".to_string(), 1)]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[&("hcl".to_string(), "hex")], 1);
+        assert_eq!(report.birth_decades.iter().map(|(d, c)| (*d, *c)).collect::<Vec<_>>(), vec![(1920, 1), (1930, 2), (1990, 1)]);
+        assert_eq!(report.eye_colors["brn"], 2);
+        assert!(report.multiple_failures.is_empty());
+
+        let report = Report::new(&Schema::parse(DEFAULT_SCHEMA).unwrap(), "hgt:59cm ecl:zzz eyr:2038\n\nbyr:1999", &ParsePolicy::default()).unwrap();
+        assert_eq!(report.multiple_failures.iter().map(|(p, e)| (*p, e.len())).collect::<Vec<_>>(), vec![(1, 7), (2, 6)]);
+        assert_eq!(report.eye_colors["zzz"], 1);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn policies() {
+        use crate::policy::{DuplicatePolicy, UnknownFieldPolicy};
+
+        let schema = Schema::parse(DEFAULT_SCHEMA).unwrap();
+        let batch = "byr:1937 iyr:2017\n\nbyr:1937 byr:1950 ppt:x";
+        let warn = ParsePolicy { unknown_fields: UnknownFieldPolicy::Warn, ..ParsePolicy::default() };
+        let report = Report::new(&schema, batch, &warn).unwrap();
+        assert_eq!(report.passports, 2);
+        assert_eq!(report.birth_decades[&1950], 1);
+        assert_eq!(report.warnings, vec![
+            (2, FieldError::DuplicateKey { key: "byr".to_string(), first: "1937".to_string(), second: "1950".to_string() }),
+            (2, FieldError::UnknownField { key: "ppt".to_string(), value: "x".to_string() })
+        ]);
+
+        let strict = ParsePolicy { duplicates: DuplicatePolicy::Error, ..ParsePolicy::default() };
+        assert_eq!(Report::new(&schema, batch, &strict).err().unwrap(), "Passport 2: Duplicate key byr, was 1937, then 1950");
+        let reject = ParsePolicy { unknown_fields: UnknownFieldPolicy::Reject, ..ParsePolicy::default() };
+        assert!(Report::new(&schema, batch, &reject).is_err());
+    }
+
+    #[test]
+    fn exports() {
+        let report = report();
+
+        let mut csv = Vec::new();
+        write_report(&report, Format::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("section,key,count,detail\nsummary,passports,4,\nsummary,valid,2,\nmissing,byr,0,\n"));
+        assert!(csv.contains("\nfailure,hcl hex,1,\n"));
+        assert!(csv.contains("\nbirth_decade,1930,2,\n"));
+
+        let mut json = Vec::new();
+        write_report(&report, Format::Json, &mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"missing\": {\"byr\": 0, \"iyr\": 0, \"eyr\": 0, \"hgt\": 1, \"hcl\": 0, \"ecl\": 0, \"pid\": 0, \"cid\": 1},"));
+        assert!(json.contains("\"failures\": [{\"field\": \"hcl\", \"kind\": \"hex\", \"count\": 1}],"));
+        assert!(json.contains("\"eye_colors\": {\"amb\": 1, \"brn\": 2, \"gry\": 1},"));
+        assert!(json.ends_with("\"multiple_failures\": [\n  ]\n}\n"));
+    }
+
+    #[test]
+    fn puzzle_input() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let report = Report::new(&Schema::parse(DEFAULT_SCHEMA).unwrap(), &input, &ParsePolicy::default()).unwrap();
+        assert_eq!(report.valid, 101);
+        assert_eq!(report.multiple_failures.len(), 110);
+        let missing_ecl = report.missing.iter().find(|(f, _)| f == "ecl").unwrap().1;
+        assert_eq!(report.eye_colors.values().sum::<usize>(), report.passports - missing_ecl);
+    }
+}
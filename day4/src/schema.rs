@@ -0,0 +1,398 @@
+use regex::Regex;
+
+use crate::Passport;
+
+/// The rules from the puzzle.
+pub const DEFAULT_SCHEMA : &str = include_str!("../schema.txt");
+
+/// Why a passport failed validation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    MissingField { field: String },
+    BadDigitCount { field: String, value: String, expected: usize },
+    NotANumber { field: String, value: String },
+    OutOfRange { field: String, value: i64, min: i64, max: i64, unit: Option<String> },
+    BadUnit { field: String, value: String },
+    BadHex { field: String, value: String },
+    NoMatch { field: String, value: String, pattern: String },
+    UnknownValue { field: String, value: String }
+}
+
+impl ValidationError {
+    pub fn field(&self) -> &str {
+        match self {
+            ValidationError::MissingField { field } |
+            ValidationError::BadDigitCount { field, .. } |
+            ValidationError::NotANumber { field, .. } |
+            ValidationError::OutOfRange { field, .. } |
+            ValidationError::BadUnit { field, .. } |
+            ValidationError::BadHex { field, .. } |
+            ValidationError::NoMatch { field, .. } |
+            ValidationError::UnknownValue { field, .. } => field
+        }
+    }
+
+    /// Short name of the check that failed, for grouping errors of different fields.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ValidationError::MissingField { .. } => "missing",
+            ValidationError::BadDigitCount { .. } => "digits",
+            ValidationError::NotANumber { .. } => "number",
+            ValidationError::OutOfRange { .. } => "range",
+            ValidationError::BadUnit { .. } => "unit",
+            ValidationError::BadHex { .. } => "hex",
+            ValidationError::NoMatch { .. } => "pattern",
+            ValidationError::UnknownValue { .. } => "value"
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::MissingField { field } => write!(f, "{} not present", field),
+            ValidationError::BadDigitCount { field, value, expected } => write!(f, "{} has invalid digit count: {} (expected {})", field, value, expected),
+            ValidationError::NotANumber { field, value } => write!(f, "{} not parseable: {}", field, value),
+            ValidationError::OutOfRange { field, value, min, max, unit } => {
+                let unit = unit.as_deref().unwrap_or("");
+                write!(f, "{} out of valid range: {}{} (expected {}{} to {}{})", field, value, unit, min, unit, max, unit)
+            },
+            ValidationError::BadUnit { field, value } => write!(f, "{} has invalid unit: {}", field, value),
+            ValidationError::BadHex { field, value } => write!(f, "{} is not a hex color: {}", field, value),
+            ValidationError::NoMatch { field, value, pattern } => write!(f, "{} does not match {}: {}", field, pattern, value),
+            ValidationError::UnknownValue { field, value } => write!(f, "{} has unknown value {}", field, value)
+        }
+    }
+}
+
+/// Checks a single field value.
+#[derive(Debug)]
+pub enum Validator {
+    Any,
+    Int {
+        range: Option<(i64, i64)>,
+        digits: Option<usize>
+    },
+    Unit(Vec<(String, i64, i64)>), // suffix and the allowed range for it
+    Hex(usize), // a '#' followed by this many lowercase hex digits
+    Regex { regex: Regex, pattern: String }, // the pattern as written, compiled to match the whole value
+    Enum(Vec<String>)
+}
+
+impl Validator {
+    /// Checks the value of the given field. The field name is only used for the error.
+    pub fn check(&self, field: &str, value: &str) -> Result<(), ValidationError> {
+        let field = field.to_string();
+        match self {
+            Validator::Any => Ok(()),
+            Validator::Int { range, digits } => {
+                if let Some(expected) = *digits {
+                    if value.len() != expected {
+                        return Err(ValidationError::BadDigitCount { field, value: value.to_string(), expected });
+                    }
+                    // exactly that many digits, so no sign either
+                    if !value.bytes().all(|b| b.is_ascii_digit()) {
+                        return Err(ValidationError::NotANumber { field, value: value.to_string() });
+                    }
+                }
+                let n = value.parse::<i64>().map_err(|_| ValidationError::NotANumber { field: field.clone(), value: value.to_string() })?;
+                match *range {
+                    Some((min, max)) if !(min..=max).contains(&n) => Err(ValidationError::OutOfRange { field, value: n, min, max, unit: None }),
+                    _ => Ok(())
+                }
+            },
+            Validator::Unit(units) => {
+                let (suffix, min, max, number) = units.iter()
+                    .find_map(|(suffix, min, max)| value.strip_suffix(suffix.as_str()).map(|number| (suffix, *min, *max, number)))
+                    .ok_or_else(|| ValidationError::BadUnit { field: field.clone(), value: value.to_string() })?;
+                if !number.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(ValidationError::NotANumber { field, value: value.to_string() });
+                }
+                let n = number.parse::<i64>().map_err(|_| ValidationError::NotANumber { field: field.clone(), value: value.to_string() })?;
+                if (min..=max).contains(&n) {
+                    Ok(())
+                }else{
+                    Err(ValidationError::OutOfRange { field, value: n, min, max, unit: Some(suffix.clone()) })
+                }
+            },
+            Validator::Hex(digits) => {
+                let valid = value.strip_prefix('#')
+                    .is_some_and(|hex| hex.len() == *digits && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')));
+                if valid {
+                    Ok(())
+                }else{
+                    Err(ValidationError::BadHex { field, value: value.to_string() })
+                }
+            },
+            Validator::Regex { regex, pattern } => {
+                if regex.is_match(value) {
+                    Ok(())
+                }else{
+                    Err(ValidationError::NoMatch { field, value: value.to_string(), pattern: pattern.clone() })
+                }
+            },
+            Validator::Enum(values) => {
+                if values.iter().any(|v| v == value) {
+                    Ok(())
+                }else{
+                    Err(ValidationError::UnknownValue { field, value: value.to_string() })
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldRule {
+    pub key: String,
+    pub required: bool,
+    pub validator: Validator
+}
+
+/// Which fields a passport needs and what their values must look like.
+#[derive(Debug)]
+pub struct Schema {
+    pub fields: Vec<FieldRule>
+}
+
+impl Schema {
+    /// Parses a schema file. See schema.txt for the syntax.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        for (line_index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|e| format!("Line {}: {}", line_index + 1, e))?;
+            if fields.iter().any(|f: &FieldRule| f.key == rule.key) {
+                return Err(format!("Line {}: Field {} is defined twice", line_index + 1, rule.key));
+            }
+            fields.push(rule);
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn required_fields(&self) -> Vec<&str> {
+        self.fields.iter().filter(|f| f.required).map(|f| f.key.as_str()).collect()
+    }
+
+    pub fn has_required_fields(&self, passport: &Passport) -> bool {
+        passport.has_required_fields(&self.required_fields())
+    }
+
+    /// Checks all fields in the schema and returns every problem found, in schema order. Fields
+    ///  that aren't in the schema are ignored.
+    pub fn validate(&self, passport: &Passport) -> Vec<ValidationError> {
+        self.fields.iter().filter_map(|rule| match passport.get_field(&rule.key) {
+            Some(value) => rule.validator.check(&rule.key, value).err(),
+            None if rule.required => Some(ValidationError::MissingField { field: rule.key.clone() }),
+            None => None
+        }).collect()
+    }
+
+    /// Whether all required fields are present and all fields in the schema are valid.
+    pub fn is_valid(&self, passport: &Passport) -> bool {
+        self.validate(passport).is_empty()
+    }
+}
+
+fn parse_rule(line: &str) -> Result<FieldRule, String> {
+    let (key, validator) = line.split_once(':').ok_or("Expected \"key: validator\"")?;
+    let key = key.trim();
+    let (key, required) = match key.strip_suffix('?') {
+        Some(key) => (key, false),
+        None => (key, true)
+    };
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("Invalid key \"{}\"", key));
+    }
+
+    let validator = validator.trim();
+    let (kind, arguments) = validator.split_once(char::is_whitespace).unwrap_or((validator, ""));
+    let arguments = arguments.trim();
+    let mut words = arguments.split_whitespace();
+    let validator = match kind {
+        "any" => Validator::Any,
+        "int" => {
+            let mut range = None;
+            let mut digits = None;
+            while let Some(word) = words.next() {
+                if word == "digits" {
+                    let count = words.next().ok_or("Expected a digit count after digits")?;
+                    digits = Some(count.parse().map_err(|_| format!("Invalid digit count {}", count))?);
+                }else{
+                    range = Some(parse_range(word)?);
+                }
+            }
+            Validator::Int { range, digits }
+        },
+        "unit" => {
+            let mut units = Vec::new();
+            while let Some(suffix) = words.next() {
+                let range = parse_range(words.next().ok_or_else(|| format!("Expected a range after unit {}", suffix))?)?;
+                units.push((suffix.to_string(), range.0, range.1));
+            }
+            if units.is_empty() {
+                return Err("Expected at least one unit".to_string());
+            }
+            Validator::Unit(units)
+        },
+        "hex" => {
+            let digits = words.next().ok_or("Expected a digit count")?;
+            Validator::Hex(digits.parse().map_err(|_| format!("Invalid digit count {}", digits))?)
+        },
+        "regex" => Validator::Regex {
+            regex: Regex::new(&format!("^(?:{})$", arguments)).map_err(|e| format!("Invalid regex: {}", e))?,
+            pattern: arguments.to_string()
+        },
+        "enum" => {
+            let values : Vec<String> = words.map(|w| w.to_string()).collect();
+            if values.is_empty() {
+                return Err("Expected at least one value".to_string());
+            }
+            Validator::Enum(values)
+        },
+        _ => return Err(format!("Unknown validator {}", kind))
+    };
+
+    Ok(FieldRule {
+        key: key.to_string(),
+        required,
+        validator
+    })
+}
+
+fn parse_range(s: &str) -> Result<(i64, i64), String> {
+    // split at the dash after the first character, so the minimum can be negative
+    let dash = s.char_indices().skip(1).find(|(_, c)| *c == '-').map(|(i, _)| i).ok_or_else(|| format!("Expected a range like 1-9, found {}", s))?;
+    let min = s[..dash].parse().map_err(|_| format!("Invalid range {}", s))?;
+    let max = s[dash + 1..].parse().map_err(|_| format!("Invalid range {}", s))?;
+    if min > max {
+        return Err(format!("Range {} is empty", s));
+    }
+    Ok((min, max))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(rule: &str, value: &str) -> bool {
+        parse_rule(rule).unwrap().validator.check("a", value).is_ok()
+    }
+
+    #[test]
+    fn validators() {
+        assert!(check("a: int 1920-2002 digits 4", "2002"));
+        assert!(!check("a: int 1920-2002 digits 4", "2003"));
+        assert!(!check("a: int 1920-2002 digits 4", "02000"));
+        assert!(check("a: int digits 9", "000000001"));
+        assert!(!check("a: int digits 9", "0123456789"));
+        assert!(!check("a: int", "12a"));
+        assert!(!check("a: int digits 4", "+192"));
+        assert!(!check("a: int 0-999999999 digits 9", "-12345678"));
+        assert!(check("a: int", "-12345678"));
+        assert!(check("a: int -5--1", "-3"));
+
+        assert!(check("a: unit cm 150-193 in 59-76", "60in"));
+        assert!(check("a: unit cm 150-193 in 59-76", "190cm"));
+        assert!(!check("a: unit cm 150-193 in 59-76", "190in"));
+        assert!(!check("a: unit cm 150-193 in 59-76", "190"));
+        assert!(!check("a: unit cm 150-193 in 59-76", "+165cm"));
+
+        assert!(check("a: hex 6", "#123abc"));
+        assert!(!check("a: hex 6", "#123abC"));
+        assert!(!check("a: hex 6", "#123ab"));
+        assert!(!check("a: hex 6", "123abc"));
+
+        assert!(check("a: regex ^#[0-9a-f]{6}$", "#123abc"));
+        assert!(!check("a: regex ^#[0-9a-f]{6}$", "#123abz"));
+        assert!(!check("a: regex ^#[0-9a-f]{6}$", "123abc"));
+        assert!(check("a: regex ^a b$", "a b"));
+        // patterns always match the whole value, anchored or not
+        assert!(check("a: regex #[0-9a-f]{6}", "#123abc"));
+        assert!(!check("a: regex #[0-9a-f]{6}", "x#123abcdef"));
+        assert!(!check("a: regex a|b", "ab"));
+
+        assert!(check("a: enum amb blu", "blu"));
+        assert!(!check("a: enum amb blu", "wat"));
+        assert!(check("a: any", ""));
+    }
+
+    #[test]
+    fn schema_errors() {
+        assert!(Schema::parse("byr int 1-2").err().unwrap().starts_with("Line 1"));
+        assert!(Schema::parse("# comment\nbyr: inf").err().unwrap().starts_with("Line 2: Unknown validator"));
+        assert!(Schema::parse("byr: int 2-1").is_err());
+        assert!(Schema::parse("byr: int digits").is_err());
+        assert!(Schema::parse("hgt: unit cm").is_err());
+        assert!(Schema::parse("hgt: unit").is_err());
+        assert!(Schema::parse("hcl: regex [").is_err());
+        assert!(Schema::parse("ecl: enum").is_err());
+        assert!(Schema::parse("hcl: hex").is_err());
+        assert!(Schema::parse("byr: any\nbyr?: any").is_err());
+        assert!(Schema::parse(": any").is_err());
+    }
+
+    #[test]
+    fn default_schema() {
+        let schema = Schema::parse(DEFAULT_SCHEMA).unwrap();
+        assert_eq!(schema.required_fields(), vec!["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"]);
+
+        // examples from the puzzle
+        let invalid = ["eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926",
+                       "iyr:2019 hcl:#602927 eyr:1967 hgt:170cm ecl:grn pid:012533040 byr:1946",
+                       "hcl:dab227 iyr:2012 ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277",
+                       "hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023 pid:3556412378 byr:2007"];
+        let valid = ["pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f",
+                     "eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm",
+                     "hcl:#888785 hgt:164cm byr:2001 iyr:2015 cid:88 pid:545766238 ecl:hzl eyr:2022",
+                     "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719"];
+        for p in invalid.iter() {
+            assert!(!schema.is_valid(&Passport::new_from_str(p)), "{}", p);
+        }
+        for p in valid.iter() {
+            assert!(schema.is_valid(&Passport::new_from_str(p)), "{}", p);
+        }
+
+        // signed passport ids have the right length, but aren't nine digits
+        for pid in ["-12345678", "+12345678"].iter() {
+            let p = Passport::new_from_str(&format!("hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f pid:{}", pid));
+            assert_eq!(schema.validate(&p), vec![ValidationError::NotANumber { field: "pid".to_string(), value: pid.to_string() }]);
+        }
+    }
+
+    #[test]
+    fn validation_errors() {
+        let schema = Schema::parse(DEFAULT_SCHEMA).unwrap();
+        let errors = schema.validate(&Passport::new_from_str("eyr:1972 cid:100 hcl:#18171 ecl:zzz hgt:170 pid:186cm iyr:201x byr:1926"));
+        assert_eq!(errors, vec![
+            ValidationError::NotANumber { field: "iyr".to_string(), value: "201x".to_string() },
+            ValidationError::OutOfRange { field: "eyr".to_string(), value: 1972, min: 2020, max: 2030, unit: None },
+            ValidationError::BadUnit { field: "hgt".to_string(), value: "170".to_string() },
+            ValidationError::BadHex { field: "hcl".to_string(), value: "#18171".to_string() },
+            ValidationError::UnknownValue { field: "ecl".to_string(), value: "zzz".to_string() },
+            ValidationError::BadDigitCount { field: "pid".to_string(), value: "186cm".to_string(), expected: 9 }
+        ]);
+
+        let errors = schema.validate(&Passport::new_from_str("hgt:194cm"));
+        assert_eq!(errors.len(), 7);
+        assert_eq!(errors[0], ValidationError::MissingField { field: "byr".to_string() });
+        assert_eq!(errors[3].to_string(), "hgt out of valid range: 194cm (expected 150cm to 193cm)");
+        assert_eq!(errors.iter().map(|e| e.field()).collect::<Vec<_>>(), vec!["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"]);
+    }
+
+    #[test]
+    fn custom_schema() {
+        let schema = Schema::parse("name: regex ^[A-Z][a-z]+$\nage?: int 0-150\n").unwrap();
+        assert!(schema.is_valid(&Passport::new_from_str("name:Ada")));
+        assert!(schema.is_valid(&Passport::new_from_str("name:Ada age:36 byr:whatever")));
+        assert!(!schema.is_valid(&Passport::new_from_str("name:Ada age:360")));
+        assert!(!schema.is_valid(&Passport::new_from_str("age:36")));
+        assert!(!schema.has_required_fields(&Passport::new_from_str("age:36")));
+        assert_eq!(schema.validate(&Passport::new_from_str("name:ada")),
+                   vec![ValidationError::NoMatch { field: "name".to_string(), value: "ada".to_string(), pattern: "^[A-Z][a-z]+$".to_string() }]);
+    }
+}
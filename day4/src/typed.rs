@@ -0,0 +1,323 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Passport;
+use crate::schema::ValidationError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeightUnit {
+    Centimeters,
+    Inches
+}
+
+impl HeightUnit {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            HeightUnit::Centimeters => "cm",
+            HeightUnit::Inches => "in"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Height {
+    pub value: u16,
+    pub unit: HeightUnit
+}
+
+impl FromStr for Height {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = if let Some(number) = s.strip_suffix("cm") {
+            (number, HeightUnit::Centimeters)
+        }else if let Some(number) = s.strip_suffix("in") {
+            (number, HeightUnit::Inches)
+        }else{
+            return Err(ValidationError::BadUnit { field: "hgt".to_string(), value: s.to_string() });
+        };
+        // only digits, parse() would also take a sign
+        let not_a_number = || ValidationError::NotANumber { field: "hgt".to_string(), value: s.to_string() };
+        if !number.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(not_a_number());
+        }
+        let value = number.parse().map_err(|_| not_a_number())?;
+        Ok(Self { value, unit })
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit.suffix())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HairColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+impl FromStr for HairColor {
+    type Err = ValidationError;
+
+    /// Parses a color like #a97842. Only lowercase digits are accepted, as in the puzzle.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ValidationError::BadHex { field: "hcl".to_string(), value: s.to_string() };
+        let hex = s.strip_prefix('#').ok_or_else(error)?;
+        if hex.len() != 6 || !hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+            return Err(error());
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i*2..i*2 + 2], 16).map_err(|_| error());
+        Ok(Self {
+            r: component(0)?,
+            g: component(1)?,
+            b: component(2)?
+        })
+    }
+}
+
+impl fmt::Display for HairColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other
+}
+
+impl EyeColor {
+    pub const ALL : [EyeColor; 7] = [EyeColor::Amber, EyeColor::Blue, EyeColor::Brown, EyeColor::Gray, EyeColor::Green, EyeColor::Hazel, EyeColor::Other];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            EyeColor::Amber => "amb",
+            EyeColor::Blue => "blu",
+            EyeColor::Brown => "brn",
+            EyeColor::Gray => "gry",
+            EyeColor::Green => "grn",
+            EyeColor::Hazel => "hzl",
+            EyeColor::Other => "oth"
+        }
+    }
+}
+
+impl FromStr for EyeColor {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EyeColor::ALL.iter().find(|c| c.code() == s).copied()
+            .ok_or_else(|| ValidationError::UnknownValue { field: "ecl".to_string(), value: s.to_string() })
+    }
+}
+
+impl fmt::Display for EyeColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Nine digits, leading zeros included. Can only be created by parsing, so it's always valid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PassportId(String);
+
+impl PassportId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for PassportId {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 9 {
+            Err(ValidationError::BadDigitCount { field: "pid".to_string(), value: s.to_string(), expected: 9 })
+        }else if !s.bytes().all(|b| b.is_ascii_digit()) {
+            Err(ValidationError::NotANumber { field: "pid".to_string(), value: s.to_string() })
+        }else{
+            Ok(Self(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for PassportId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A passport that passed validation, with all values parsed. Fields other than the ones from
+///  the puzzle are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedPassport {
+    pub birth_year: u16,
+    pub issue_year: u16,
+    pub expiration_year: u16,
+    pub height: Height,
+    pub hair_color: HairColor,
+    pub eye_color: EyeColor,
+    pub passport_id: PassportId,
+    pub country_id: Option<String>
+}
+
+/// Parses an optional field, adding an error if it can't be parsed and the validation didn't
+///  complain about that field already.
+fn parse_field<T: FromStr>(passport: &Passport, key: &str, errors: &mut Vec<ValidationError>, error: impl FnOnce(T::Err) -> ValidationError) -> Option<T> {
+    let value = passport.get_field(key)?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            if !errors.iter().any(|e| e.field() == key) {
+                errors.push(error(e));
+            }
+            None
+        }
+    }
+}
+
+impl TryFrom<&Passport> for TypedPassport {
+    type Error = Vec<ValidationError>;
+
+    /// Validates the passport against the puzzle rules and parses all fields. Returns all
+    ///  problems found if that fails.
+    fn try_from(passport: &Passport) -> Result<Self, Self::Error> {
+        let mut errors = passport.validate();
+
+        let year = |key: &str, errors: &mut Vec<ValidationError>| {
+            parse_field::<u16>(passport, key, errors, |_| ValidationError::NotANumber { field: key.to_string(), value: passport.get_field(key).unwrap().to_string() })
+        };
+        let birth_year = year("byr", &mut errors);
+        let issue_year = year("iyr", &mut errors);
+        let expiration_year = year("eyr", &mut errors);
+        let height = parse_field(passport, "hgt", &mut errors, |e| e);
+        let hair_color = parse_field(passport, "hcl", &mut errors, |e| e);
+        let eye_color = parse_field(passport, "ecl", &mut errors, |e| e);
+        let passport_id = parse_field(passport, "pid", &mut errors, |e| e);
+
+        match (birth_year, issue_year, expiration_year, height, hair_color, eye_color, passport_id) {
+            (Some(birth_year), Some(issue_year), Some(expiration_year), Some(height), Some(hair_color), Some(eye_color), Some(passport_id)) if errors.is_empty() => {
+                Ok(Self {
+                    birth_year,
+                    issue_year,
+                    expiration_year,
+                    height,
+                    hair_color,
+                    eye_color,
+                    passport_id,
+                    country_id: passport.get_field("cid").map(|c| c.to_string())
+                })
+            },
+            _ => Err(errors)
+        }
+    }
+}
+
+impl TryFrom<Passport> for TypedPassport {
+    type Error = Vec<ValidationError>;
+
+    fn try_from(passport: Passport) -> Result<Self, Self::Error> {
+        Self::try_from(&passport)
+    }
+}
+
+impl FromStr for TypedPassport {
+    type Err = Vec<ValidationError>;
+
+    /// Parses a passport in the puzzle's text format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(&Passport::new_from_str(s))
+    }
+}
+
+impl fmt::Display for TypedPassport {
+    /// Writes the passport in the puzzle's text format, on a single line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "byr:{} iyr:{} eyr:{} hgt:{} hcl:{} ecl:{} pid:{}", self.birth_year, self.issue_year, self.expiration_year,
+            self.height, self.hair_color, self.eye_color, self.passport_id)?;
+        if let Some(cid) = &self.country_id {
+            write!(f, " cid:{}", cid)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_values() {
+        assert_eq!("183cm".parse(), Ok(Height { value: 183, unit: HeightUnit::Centimeters }));
+        assert_eq!("60in".parse::<Height>().unwrap().to_string(), "60in");
+        assert!("183".parse::<Height>().is_err());
+        assert!("xxcm".parse::<Height>().is_err());
+        assert!("+165cm".parse::<Height>().is_err());
+
+        assert_eq!("#a97842".parse(), Ok(HairColor { r: 0xa9, g: 0x78, b: 0x42 }));
+        assert_eq!("#00ff0a".parse::<HairColor>().unwrap().to_string(), "#00ff0a");
+        assert!("#A97842".parse::<HairColor>().is_err());
+        assert!("a97842".parse::<HairColor>().is_err());
+        assert!("#a9784".parse::<HairColor>().is_err());
+
+        assert_eq!("hzl".parse(), Ok(EyeColor::Hazel));
+        assert!("zzz".parse::<EyeColor>().is_err());
+        for color in EyeColor::ALL.iter() {
+            assert_eq!(color.to_string().parse(), Ok(*color));
+        }
+
+        assert_eq!("000000001".parse::<PassportId>().unwrap().as_str(), "000000001");
+        assert!("0123456789".parse::<PassportId>().is_err());
+        assert!("+12345678".parse::<PassportId>().is_err());
+    }
+
+    #[test]
+    fn typed_passports() {
+        let p : TypedPassport = "eyr:2029 ecl:blu cid:129 byr:1989\niyr:2014 pid:896056539 hcl:#a97842 hgt:165cm".parse().unwrap();
+        assert_eq!(p.birth_year, 1989);
+        assert_eq!(p.height, Height { value: 165, unit: HeightUnit::Centimeters });
+        assert_eq!(p.eye_color, EyeColor::Blue);
+        assert_eq!(p.country_id.as_deref(), Some("129"));
+        assert_eq!(p.to_string(), "byr:1989 iyr:2014 eyr:2029 hgt:165cm hcl:#a97842 ecl:blu pid:896056539 cid:129");
+
+        let errors = "hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023 pid:3556412378 byr:2007".parse::<TypedPassport>().unwrap_err();
+        assert_eq!(errors.len(), 7);
+        assert_eq!(errors, Passport::new_from_str("hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023 pid:3556412378 byr:2007").validate());
+
+        // the schema and the typed passport agree on signed ids
+        let signed = Passport::new_from_str("hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f pid:-12345678");
+        assert!(!signed.is_valid());
+        assert_eq!(TypedPassport::try_from(&signed).unwrap_err(), signed.validate());
+        let signed = Passport::new_from_str("hgt:+165cm ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f pid:087499704");
+        assert_eq!(signed.validate(), vec![ValidationError::NotANumber { field: "hgt".to_string(), value: "+165cm".to_string() }]);
+        assert_eq!(TypedPassport::try_from(&signed).unwrap_err(), signed.validate());
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let mut valid = 0;
+        for passport in input.split("\n\n") {
+            let raw = Passport::new_from_str(passport);
+            if let Ok(typed) = TypedPassport::try_from(&raw) {
+                valid += 1;
+                assert_eq!(typed.to_string().parse(), Ok(typed.clone()));
+                for key in ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"].iter() {
+                    assert_eq!(Passport::new_from_str(&typed.to_string()).get_field(key), raw.get_field(key));
+                }
+            }
+        }
+        assert_eq!(valid, 101);
+    }
+}